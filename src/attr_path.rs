@@ -0,0 +1,133 @@
+//! Lexing and printing of attribute paths like `.nixosConfigurations."example.com".config`.
+//!
+//! A path is printed as its attribute names joined by `.`, where names that are not plain Nix
+//! identifiers are quoted and escaped like Nix strings. The empty name standing for the root
+//! expression is printed as nothing, so paths starting at the root start with a `.`.
+
+use crate::eval::nix_string;
+
+const KEYWORDS: &[&str] = &[
+	"assert", "else", "if", "in", "inherit", "let", "or", "rec", "then", "with",
+];
+
+/// Whether `name` can be written in an attribute path without quotes, following the `ID` rule of
+/// the Nix lexer
+pub fn is_identifier(name: &str) -> bool {
+	let mut chars = name.chars();
+	chars
+		.next()
+		.is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+		&& chars.all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '\'' | '-'))
+		&& !KEYWORDS.contains(&name)
+}
+
+/// A single attribute name as it is written in an attribute path
+pub fn print_name(name: &str) -> String {
+	if is_identifier(name) {
+		name.to_string()
+	} else {
+		nix_string(name)
+	}
+}
+
+/// Names joined by `.`, with an empty first name printed as nothing. [`lex`] turns the result
+/// back into the same names.
+pub fn print(names: &[String]) -> String {
+	let mut printed = names.iter().map(|x| print_name(x)).collect::<Vec<_>>();
+	if let Some(first) = names.first().filter(|x| x.is_empty()) {
+		printed[0] = first.clone();
+	}
+	printed.join(".")
+}
+
+/// Split an attribute path into its names. This never fails, as paths are parsed while they
+/// are typed: unquoted names may be empty or contain anything but `.` and `"`, and a missing
+/// closing quote ends the name at the end of the input.
+pub fn lex(path: &str) -> Vec<String> {
+	let mut names = vec![];
+	let mut name = String::new();
+	let mut chars = path.chars();
+	while let Some(c) = chars.next() {
+		match c {
+			'.' => names.push(std::mem::take(&mut name)),
+			'"' => {
+				while let Some(c) = chars.next() {
+					match c {
+						'"' => break,
+						'\\' => match chars.next() {
+							Some('n') => name.push('\n'),
+							Some('r') => name.push('\r'),
+							Some('t') => name.push('\t'),
+							Some(c) => name.push(c),
+							None => name.push('\\'),
+						},
+						c => name.push(c),
+					}
+				}
+			}
+			c => name.push(c),
+		}
+	}
+	names.push(name);
+	names
+}
+
+/// The path as the worker reads it: a JSON array of the names on a single line, without the
+/// empty name of the root. The worker selects each name as it is, so any name can be reached.
+pub fn print_for_worker(names: &[String]) -> String {
+	let names = match names.first() {
+		Some(x) if x.is_empty() => &names[1..],
+		_ => names,
+	};
+	serde_json::to_string(names).expect("names are always valid JSON")
+}
+
+#[test]
+pub fn test_attr_path() {
+	let names = |x: &[&str]| x.iter().map(|x| x.to_string()).collect::<Vec<_>>();
+	assert_eq!(print(&names(&[""])), "");
+	assert_eq!(print(&names(&["", "a", "b-c'"])), ".a.b-c'");
+	assert_eq!(
+		print(&names(&[
+			"",
+			"foo bar",
+			"with\"quote",
+			"${x}",
+			"1st",
+			"",
+			"in"
+		])),
+		r#"."foo bar"."with\"quote"."\${x}"."1st".""."in""#
+	);
+	assert_eq!(lex(r#".a."b.c\n""#), names(&["", "a", "b.c\n"]));
+	assert_eq!(
+		lex(r#".a."unterminated"#),
+		names(&["", "a", "unterminated"])
+	);
+	assert_eq!(lex(".a."), names(&["", "a", ""]));
+	assert_eq!(print_for_worker(&names(&[""])), "[]");
+	assert_eq!(
+		print_for_worker(&names(&["", "hosts", "example.com", "a b"])),
+		r#"["hosts","example.com","a b"]"#
+	);
+	let printed = print_for_worker(&names(&["", "with\"quote", "a.b", "line\nbreak", ""]));
+	assert!(!printed.contains('\n'));
+	assert_eq!(
+		serde_json::from_str::<Vec<String>>(&printed).unwrap(),
+		names(&["with\"quote", "a.b", "line\nbreak", ""])
+	);
+}
+
+#[cfg(test)]
+proptest::proptest! {
+	#[test]
+	fn test_lex_inverts_print(names in proptest::collection::vec(proptest::prelude::any::<String>(), 1..6)) {
+		proptest::prop_assert_eq!(lex(&print(&names)), names);
+	}
+
+	#[test]
+	fn test_print_keeps_lexed_names(path in "[.\"\\\\a-z$ {}]*") {
+		let names = lex(&path);
+		proptest::prop_assert_eq!(lex(&print(&names)), names);
+	}
+}
@@ -0,0 +1,293 @@
+use std::{
+	collections::{BTreeMap, HashMap},
+	time::{Duration, Instant},
+};
+
+use ratatui::{
+	layout::{Constraint, Direction, Layout, Rect},
+	text::{Line, Span},
+	widgets::{Block, Borders, Clear, Paragraph, Row, Table, TableState, Widget},
+	Frame,
+};
+
+use crate::{
+	model::{BrowserPath, PathData, PathDataMap},
+	theme::theme,
+};
+
+/// Every child of a list evaluated at once with `E`, shown as a table once they come in
+#[derive(Debug)]
+pub struct BatchState {
+	pub path: BrowserPath,
+	pub children: Vec<String>,
+	started: Instant,
+	/// When the worker started on each child, which it announces by sending `Loading`
+	started_at: HashMap<String, Instant>,
+	pub durations: HashMap<String, Duration>,
+	/// Time until the last child was evaluated
+	pub total: Option<Duration>,
+	/// Name of the child under the cursor, as the rows move around while they are sorted
+	pub selected: Option<String>,
+	pub table_state: TableState,
+}
+
+/// A child as listed in the table
+#[derive(Debug, PartialEq)]
+pub struct BatchRow {
+	pub name: String,
+	pub data_type: String,
+	/// Value of its `enable` attribute, if it has one
+	pub enabled: Option<bool>,
+	/// First line of the error it evaluated to
+	pub error: Option<String>,
+	pub duration: Option<Duration>,
+}
+
+impl BatchState {
+	pub fn new(path: BrowserPath, children: Vec<String>) -> BatchState {
+		BatchState {
+			path,
+			total: children.is_empty().then_some(Duration::ZERO),
+			started: Instant::now(),
+			started_at: HashMap::new(),
+			durations: HashMap::new(),
+			selected: children.first().cloned(),
+			table_state: TableState::default(),
+			children,
+		}
+	}
+
+	/// Name of the child `path` is, if it is one
+	pub fn child_name<'a>(&self, path: &'a BrowserPath) -> Option<&'a String> {
+		match path.0.split_last() {
+			Some((name, parent)) if parent == self.path.0.as_slice() => Some(name),
+			_ => None,
+		}
+	}
+
+	/// Note the time of a result from the worker
+	pub fn record(&mut self, path: &BrowserPath, data: &PathData) {
+		let Some(name) = self.child_name(path) else {
+			return;
+		};
+		let now = Instant::now();
+		match data {
+			PathData::Loading => {
+				self.started_at.insert(name.clone(), now);
+			}
+			_ => {
+				let started = self.started_at.get(name).copied().unwrap_or(self.started);
+				self.durations.insert(name.clone(), now - started);
+				if self.durations.len() == self.children.len() {
+					self.total = Some(now - self.started);
+				}
+			}
+		}
+	}
+
+	/// The children, slowest first, with the ones still being evaluated at the end
+	pub fn rows(&self, path_data: &PathDataMap) -> Vec<BatchRow> {
+		let mut rows = self
+			.children
+			.iter()
+			.map(|name| {
+				let child = self.path.child(name.clone());
+				let duration = self.durations.get(name).copied();
+				let data = path_data.get(&child).filter(|_| duration.is_some());
+				let enabled = match path_data.get(&child.child("enable".to_string())) {
+					Some(PathData::Bool(x)) => Some(*x),
+					_ => None,
+				};
+				BatchRow {
+					name: name.clone(),
+					data_type: data.map_or("…".to_string(), |x| x.get_type()),
+					enabled,
+					error: match data {
+						Some(PathData::Error(e)) => e.lines().next().map(|x| x.to_string()),
+						_ => None,
+					},
+					duration,
+				}
+			})
+			.collect::<Vec<_>>();
+		rows.sort_by_key(|x| (x.duration.is_none(), std::cmp::Reverse(x.duration)));
+		rows
+	}
+
+	/// Move the cursor by `delta` rows in the current order of the table
+	pub fn move_by(&mut self, delta: isize, path_data: &PathDataMap) {
+		let rows = self.rows(path_data);
+		let last = rows.len().saturating_sub(1);
+		let i = self.selected_row(&rows).unwrap_or(0);
+		let i = i.saturating_add_signed(delta).min(last);
+		self.selected = rows.get(i).map(|x| x.name.clone());
+	}
+
+	fn selected_row(&self, rows: &[BatchRow]) -> Option<usize> {
+		rows.iter()
+			.position(|x| Some(&x.name) == self.selected.as_ref())
+	}
+}
+
+/// Summary lines above the table: counts by type, errors and enabled children
+fn summary(rows: &[BatchRow]) -> Vec<Line<'static>> {
+	let mut counts = BTreeMap::new();
+	for row in rows.iter().filter(|x| x.duration.is_some()) {
+		*counts.entry(row.data_type.clone()).or_insert(0) += 1;
+	}
+	let counts = counts
+		.iter()
+		.map(|(data_type, n)| format!("{n} {data_type}"))
+		.collect::<Vec<_>>()
+		.join(", ");
+	let names = |f: fn(&BatchRow) -> bool| {
+		let names = rows
+			.iter()
+			.filter(|x| f(x))
+			.map(|x| x.name.as_str())
+			.collect::<Vec<_>>();
+		match names.len() {
+			0 => "none".to_string(),
+			n => format!("{n}: {}", names.join(", ")),
+		}
+	};
+	let line = |name: &'static str, value: String| {
+		Line::from(vec![
+			Span::styled(format!("{name:9}"), theme().heading),
+			Span::raw(value),
+		])
+	};
+	vec![
+		line("Types", counts),
+		line("Errors", names(|x| x.error.is_some())),
+		line("Enabled", names(|x| x.enabled == Some(true))),
+	]
+}
+
+fn format_duration(x: Duration) -> String {
+	if x < Duration::from_secs(1) {
+		format!("{}ms", x.as_millis())
+	} else {
+		format!("{:.1}s", x.as_secs_f64())
+	}
+}
+
+/// Overlay with the summary and a table of every child of the batch
+pub fn render_batch(f: &mut Frame, batch: &mut BatchState, path_data: &PathDataMap, outer: Rect) {
+	let rows = batch.rows(path_data);
+	let done = rows.iter().filter(|x| x.duration.is_some()).count();
+	let title = match batch.total {
+		Some(total) => format!(
+			"{} children of {} evaluated in {}",
+			rows.len(),
+			batch.path.to_expr(),
+			format_duration(total)
+		),
+		None => format!(
+			"Evaluating {} children of {}: {done} done",
+			rows.len(),
+			batch.path.to_expr()
+		),
+	};
+
+	let width = outer.width.saturating_sub(4).max(outer.width.min(20));
+	let height = outer.height.saturating_sub(4).max(outer.height.min(8));
+	let rect = Rect::new(
+		outer.x + (outer.width - width) / 2,
+		outer.y + (outer.height - height) / 2,
+		width,
+		height,
+	);
+	let block = Block::default()
+		.borders(Borders::ALL)
+		.title(title)
+		.title_style(theme().title);
+	let inner = block.inner(rect);
+	Clear.render(rect, f.buffer_mut());
+	f.render_widget(block, rect);
+	let parts = Layout::default()
+		.direction(Direction::Vertical)
+		.constraints([Constraint::Length(4), Constraint::Fill(1)])
+		.split(inner);
+	f.render_widget(Paragraph::new(summary(&rows)), parts[0]);
+
+	let name_width = rows.iter().map(|x| x.name.len()).max().unwrap_or(0).max(4) as u16;
+	let table_rows = rows.iter().map(|row| {
+		let enabled = match row.enabled {
+			Some(true) => "yes",
+			Some(false) => "no",
+			None => "",
+		};
+		let data_style = match row.error {
+			Some(_) => theme().error,
+			None => theme().text,
+		};
+		Row::new(vec![
+			Line::raw(row.name.clone()),
+			Line::styled(row.data_type.clone(), data_style),
+			Line::raw(enabled),
+			Line::raw(row.duration.map(format_duration).unwrap_or_default()).right_aligned(),
+			Line::styled(row.error.clone().unwrap_or_default(), theme().error),
+		])
+	});
+	let table = Table::new(
+		table_rows,
+		[
+			Constraint::Length(name_width.min(inner.width / 3)),
+			Constraint::Length(8),
+			Constraint::Length(7),
+			Constraint::Length(7),
+			Constraint::Fill(1),
+		],
+	)
+	.header(Row::new(["Name", "Type", "Enabled", "Time", "Error"]).style(theme().heading))
+	.highlight_style(theme().selected);
+	batch.table_state.select(batch.selected_row(&rows));
+	f.render_stateful_widget(table, parts[1], &mut batch.table_state);
+}
+
+#[test]
+pub fn test_batch_rows() {
+	let path = |x: &str| BrowserPath::from(x.to_string());
+	let mut batch = BatchState::new(path(".services"), vec!["a".into(), "b".into(), "c".into()]);
+	let mut path_data = PathDataMap::default();
+	path_data.insert(path(".services.a.enable"), PathData::Bool(true));
+	for (name, data) in [
+		("a", PathData::Int(1)),
+		("b", PathData::Error("boom\ntrace".to_string())),
+	] {
+		batch.record(&path(&format!(".services.{name}")), &PathData::Loading);
+		path_data.insert(path(&format!(".services.{name}")), data.clone());
+		batch.record(&path(&format!(".services.{name}")), &data);
+	}
+	// Not a child
+	batch.record(&path(".services.a.enable"), &PathData::Bool(true));
+	assert_eq!(batch.total, None);
+
+	let rows = batch.rows(&path_data);
+	assert_eq!(rows.len(), 3);
+	assert_eq!(rows[2].name, "c");
+	assert_eq!(rows[2].duration, None);
+	let row = |name| rows.iter().find(|x| x.name == name).unwrap();
+	assert_eq!(row("a").enabled, Some(true));
+	assert_eq!(row("b").error.as_deref(), Some("boom"));
+	assert_eq!(row("b").data_type, "Error");
+
+	batch.record(&path(".services.c"), &PathData::Null);
+	assert!(batch.total.is_some());
+
+	// The cursor stays on the same child when the order changes
+	for (name, millis) in [("a", 1), ("b", 2), ("c", 3)] {
+		batch
+			.durations
+			.insert(name.to_string(), Duration::from_millis(millis));
+	}
+	batch.move_by(10, &path_data);
+	assert_eq!(batch.selected.as_deref(), Some("a"));
+	batch
+		.durations
+		.insert("a".to_string(), Duration::from_millis(4));
+	// `a` is now the slowest, first in the table
+	batch.move_by(1, &path_data);
+	assert_eq!(batch.selected.as_deref(), Some("c"));
+}
@@ -0,0 +1,360 @@
+//! Bookmarks are kept per root expression, so the ones of one flake don't show up when another
+//! is inspected. Global bookmarks are shown for every root. Bookmarks with a group are listed
+//! below a heading that can be collapsed.
+
+use std::{
+	collections::{BTreeMap, BTreeSet},
+	path::Path,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+	model::{Bookmark, BrowserPath},
+	stable_hash::fnv1a,
+};
+
+/// The bookmarks in `config.json`
+#[derive(Serialize, Deserialize, Default, Debug, Clone)]
+pub struct BookmarkConfig {
+	/// Shown for every root. Bookmarks saved before they were kept per root end up here.
+	#[serde(default, rename = "bookmarks")]
+	pub global: Vec<Bookmark>,
+	/// Bookmarks of each root, by [`root_id`]
+	#[serde(
+		default,
+		rename = "root_bookmarks",
+		skip_serializing_if = "BTreeMap::is_empty"
+	)]
+	pub per_root: BTreeMap<String, Vec<Bookmark>>,
+	/// Groups whose bookmarks are hidden
+	#[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+	pub collapsed_groups: BTreeSet<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BookmarkScope {
+	/// Only shown for the root expression it was saved for
+	Root,
+	Global,
+}
+
+impl BookmarkScope {
+	pub fn other(self) -> BookmarkScope {
+		match self {
+			BookmarkScope::Root => BookmarkScope::Global,
+			BookmarkScope::Global => BookmarkScope::Root,
+		}
+	}
+
+	pub fn name(self) -> &'static str {
+		match self {
+			BookmarkScope::Root => "this root",
+			BookmarkScope::Global => "global",
+		}
+	}
+}
+
+/// A bookmark in the list, by its scope and position within the scope
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BookmarkRef {
+	pub scope: BookmarkScope,
+	pub index: usize,
+}
+
+/// A line of the bookmark list
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BookmarkRow {
+	/// Heading of a group, followed by its bookmarks unless it is collapsed
+	Group {
+		name: String,
+		collapsed: bool,
+		len: usize,
+	},
+	Bookmark(BookmarkRef),
+}
+
+/// Part of a bookmark changed from the input line
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BookmarkField {
+	Name,
+	Path,
+	Group,
+	Note,
+}
+
+impl BookmarkField {
+	pub fn prompt(self) -> &'static str {
+		match self {
+			BookmarkField::Name => "rename bookmark",
+			BookmarkField::Path => "bookmark path",
+			BookmarkField::Group => "bookmark group (empty for none)",
+			BookmarkField::Note => "bookmark note",
+		}
+	}
+
+	/// The current value, as it starts out in the input line
+	pub fn get(self, bookmark: &Bookmark) -> String {
+		match self {
+			BookmarkField::Name => bookmark.name.clone(),
+			BookmarkField::Path => bookmark.path.to_string(),
+			BookmarkField::Group => bookmark.group.clone().unwrap_or_default(),
+			BookmarkField::Note => bookmark.note.clone().unwrap_or_default(),
+		}
+	}
+
+	/// Set the field from the input line. An empty name or path is ignored, an empty group or
+	/// note removes it.
+	pub fn set(self, bookmark: &mut Bookmark, input: &str) {
+		let input = input.trim();
+		let optional = (!input.is_empty()).then(|| input.to_string());
+		match self {
+			BookmarkField::Name if !input.is_empty() => bookmark.name = input.to_string(),
+			BookmarkField::Path if !input.is_empty() => {
+				bookmark.path = BrowserPath::from(input.to_string())
+			}
+			BookmarkField::Group => bookmark.group = optional,
+			BookmarkField::Note => bookmark.note = optional,
+			BookmarkField::Name | BookmarkField::Path => {}
+		}
+	}
+}
+
+/// Identifier of the root expression its bookmarks are stored under: the directory of the
+/// config, the URL of a flake given with `--expr`, or a hash of any other expression
+pub fn root_id(expr: &str, root: Option<&Path>) -> String {
+	if let Some(root) = root {
+		return root.display().to_string();
+	}
+	let flake_url = expr
+		.trim()
+		.strip_prefix("builtins.getFlake")
+		.map(|x| x.trim())
+		.and_then(|x| x.strip_prefix('"')?.strip_suffix('"'))
+		.filter(|x| !x.contains('"'));
+	match flake_url {
+		Some(url) => url.to_string(),
+		None => format!("expr:{:016x}", fnv1a(expr.trim().as_bytes())),
+	}
+}
+
+impl BookmarkConfig {
+	pub fn scope(&self, scope: BookmarkScope, root_id: &str) -> &[Bookmark] {
+		match scope {
+			BookmarkScope::Root => self.per_root.get(root_id).map_or(&[], |x| x.as_slice()),
+			BookmarkScope::Global => &self.global,
+		}
+	}
+
+	pub fn scope_mut(&mut self, scope: BookmarkScope, root_id: &str) -> &mut Vec<Bookmark> {
+		match scope {
+			BookmarkScope::Root => self.per_root.entry(root_id.to_string()).or_default(),
+			BookmarkScope::Global => &mut self.global,
+		}
+	}
+
+	/// Bookmarks of the root followed by the global ones, in the order they are listed
+	pub fn list(&self, root_id: &str) -> Vec<BookmarkRef> {
+		[BookmarkScope::Root, BookmarkScope::Global]
+			.iter()
+			.flat_map(|&scope| {
+				(0..self.scope(scope, root_id).len()).map(move |index| BookmarkRef { scope, index })
+			})
+			.collect()
+	}
+
+	pub fn get(&self, x: BookmarkRef, root_id: &str) -> Option<&Bookmark> {
+		self.scope(x.scope, root_id).get(x.index)
+	}
+
+	pub fn get_mut(&mut self, x: BookmarkRef, root_id: &str) -> Option<&mut Bookmark> {
+		match x.scope {
+			BookmarkScope::Root => self.per_root.get_mut(root_id)?.get_mut(x.index),
+			BookmarkScope::Global => self.global.get_mut(x.index),
+		}
+	}
+
+	/// The lines of the bookmark list: bookmarks without a group, then every group in the order
+	/// its first bookmark is listed in
+	pub fn rows(&self, root_id: &str) -> Vec<BookmarkRow> {
+		let list = self.list(root_id);
+		let group_of = |x: &BookmarkRef| self.get(*x, root_id).and_then(|x| x.group.as_ref());
+		let mut groups: Vec<&String> = vec![];
+		for group in list.iter().filter_map(group_of) {
+			if !groups.contains(&group) {
+				groups.push(group);
+			}
+		}
+
+		let mut rows = list
+			.iter()
+			.filter(|x| group_of(x).is_none())
+			.map(|x| BookmarkRow::Bookmark(*x))
+			.collect::<Vec<_>>();
+		for group in groups {
+			let members = list
+				.iter()
+				.filter(|x| group_of(x) == Some(group))
+				.collect::<Vec<_>>();
+			let collapsed = self.collapsed_groups.contains(group);
+			rows.push(BookmarkRow::Group {
+				name: group.clone(),
+				collapsed,
+				len: members.len(),
+			});
+			if !collapsed {
+				rows.extend(members.into_iter().map(|x| BookmarkRow::Bookmark(*x)));
+			}
+		}
+		rows
+	}
+
+	pub fn toggle_collapsed(&mut self, group: &str) {
+		if !self.collapsed_groups.remove(group) {
+			self.collapsed_groups.insert(group.to_string());
+		}
+	}
+
+	/// Swap a bookmark with the next one up or down in the same scope and group, returns where
+	/// it ended up
+	pub fn move_by(&mut self, x: BookmarkRef, delta: isize, root_id: &str) -> Option<BookmarkRef> {
+		let bookmarks = self.scope_mut(x.scope, root_id);
+		let group = bookmarks.get(x.index)?.group.clone();
+		let same_group = |i: &usize| bookmarks[*i].group == group;
+		let other = if delta < 0 {
+			(0..x.index).rev().find(same_group)
+		} else {
+			(x.index + 1..bookmarks.len()).find(same_group)
+		}?;
+		bookmarks.swap(x.index, other);
+		Some(BookmarkRef {
+			scope: x.scope,
+			index: other,
+		})
+	}
+
+	pub fn remove(&mut self, x: BookmarkRef, root_id: &str) -> Option<Bookmark> {
+		let bookmarks = self.scope_mut(x.scope, root_id);
+		let removed = (x.index < bookmarks.len()).then(|| bookmarks.remove(x.index));
+		if self.per_root.get(root_id).is_some_and(|x| x.is_empty()) {
+			self.per_root.remove(root_id);
+		}
+		removed
+	}
+
+	/// Move a bookmark to the end of the other scope, returns where it ended up
+	pub fn toggle_scope(&mut self, x: BookmarkRef, root_id: &str) -> Option<BookmarkRef> {
+		let bookmark = self.remove(x, root_id)?;
+		let scope = x.scope.other();
+		let bookmarks = self.scope_mut(scope, root_id);
+		bookmarks.push(bookmark);
+		Some(BookmarkRef {
+			scope,
+			index: bookmarks.len() - 1,
+		})
+	}
+}
+
+#[test]
+pub fn test_bookmark_scopes() {
+	let bookmark = |name: &str| Bookmark {
+		name: name.to_string(),
+		path: BrowserPath::from(format!(".{name}")),
+		group: None,
+		note: None,
+	};
+	let mut config: BookmarkConfig =
+		serde_json::from_str(r#"{"bookmarks": [{"display": "a", "path": ".a"}], "keymap": {}}"#)
+			.unwrap();
+	assert_eq!(config.global.len(), 1);
+	config
+		.scope_mut(BookmarkScope::Root, "/etc/nixos")
+		.push(bookmark("b"));
+	config
+		.scope_mut(BookmarkScope::Root, "/other")
+		.push(bookmark("c"));
+
+	let names = |config: &BookmarkConfig, root_id| {
+		config
+			.list(root_id)
+			.into_iter()
+			.map(|x| config.get(x, root_id).unwrap().name.clone())
+			.collect::<Vec<_>>()
+	};
+	assert_eq!(names(&config, "/etc/nixos"), ["b", "a"]);
+	assert_eq!(names(&config, "/other"), ["c", "a"]);
+
+	let global = config.list("/etc/nixos")[1];
+	let moved = config.toggle_scope(global, "/etc/nixos");
+	assert_eq!(
+		moved,
+		Some(BookmarkRef {
+			scope: BookmarkScope::Root,
+			index: 1
+		})
+	);
+	assert_eq!(names(&config, "/etc/nixos"), ["b", "a"]);
+	assert_eq!(names(&config, "/other"), ["c"]);
+
+	let json = serde_json::to_string(&config).unwrap();
+	assert!(json.contains(r#""root_bookmarks":{"/etc/nixos":"#));
+
+	assert_eq!(
+		root_id(r#"builtins.getFlake "github:owner/repo""#, None),
+		"github:owner/repo"
+	);
+	assert_eq!(root_id("1 + 1", None), root_id(" 1 + 1 ", None));
+	assert_eq!(root_id("1 + 1", None), "expr:82adf0fcdf821f32");
+}
+
+#[test]
+pub fn test_bookmark_groups() {
+	// The format before bookmarks had groups and notes
+	let mut config: BookmarkConfig = serde_json::from_str(
+		r#"{"bookmarks": [
+			{"display": "a", "path": ".a"},
+			{"display": "b", "path": ".b"},
+			{"display": "c", "path": ".c"},
+			{"display": "d", "path": ".d"}
+		]}"#,
+	)
+	.unwrap();
+	let global = |index| BookmarkRef {
+		scope: BookmarkScope::Global,
+		index,
+	};
+	let names = |config: &BookmarkConfig| {
+		config
+			.rows("")
+			.into_iter()
+			.map(|row| match row {
+				BookmarkRow::Group { name, len, .. } => format!("{name} ({len})"),
+				BookmarkRow::Bookmark(x) => config.get(x, "").unwrap().name.clone(),
+			})
+			.collect::<Vec<_>>()
+	};
+	assert_eq!(names(&config), ["a", "b", "c", "d"]);
+
+	for i in [0, 2] {
+		let bookmark = config.get_mut(global(i), "").unwrap();
+		BookmarkField::Group.set(bookmark, " hosts ");
+	}
+	BookmarkField::Name.set(config.get_mut(global(3), "").unwrap(), "");
+	BookmarkField::Note.set(config.get_mut(global(3), "").unwrap(), "why");
+	assert_eq!(names(&config), ["b", "d", "hosts (2)", "a", "c"]);
+	assert_eq!(config.global[3].note.as_deref(), Some("why"));
+
+	// `a` skips over `b`, which is in no group
+	assert_eq!(config.move_by(global(0), 1, ""), Some(global(2)));
+	assert_eq!(names(&config), ["b", "d", "hosts (2)", "c", "a"]);
+	assert_eq!(config.move_by(global(2), 1, ""), None);
+
+	config.toggle_collapsed("hosts");
+	assert_eq!(names(&config), ["b", "d", "hosts (2)"]);
+
+	let json = serde_json::to_value(&config).unwrap();
+	assert_eq!(json["bookmarks"][0]["name"], "c");
+	assert_eq!(json["bookmarks"][0]["group"], "hosts");
+	assert!(json["bookmarks"][1].get("group").is_none());
+	assert_eq!(json["collapsed_groups"], serde_json::json!(["hosts"]));
+}
@@ -0,0 +1,126 @@
+//! Memory accounting for the evaluated values kept in the `PathDataMap`. Once they take up more
+//! than the configured limit, the least recently used ones are dropped and evaluated again when
+//! they are needed.
+
+use std::mem::size_of;
+
+use ratatui::{
+	layout::Rect,
+	text::{Line, Span},
+	widgets::{Block, Borders, Clear, Paragraph, Widget},
+	Frame,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+	model::{BrowserPath, PathData},
+	theme::theme,
+};
+
+/// The `cache` section of `config.json`
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(default)]
+pub struct CacheConfig {
+	/// Memory in MiB the evaluated values may take up before the least recently used ones are
+	/// dropped
+	pub max_mb: usize,
+	/// Save the values between runs and show them until they are evaluated again
+	pub disk: bool,
+}
+
+impl Default for CacheConfig {
+	fn default() -> Self {
+		CacheConfig {
+			max_mb: 256,
+			disk: false,
+		}
+	}
+}
+
+impl CacheConfig {
+	pub fn is_default(&self) -> bool {
+		*self == CacheConfig::default()
+	}
+
+	pub fn max_bytes(&self) -> usize {
+		self.max_mb.saturating_mul(1024 * 1024)
+	}
+}
+
+/// Counters shown in the cache panel, toggled with `<F12>`
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct CacheStats {
+	pub entries: usize,
+	pub bytes: usize,
+	pub max_bytes: Option<usize>,
+	/// Lookups of values that were already evaluated or being evaluated
+	pub hits: u64,
+	/// Lookups that had to ask the worker
+	pub misses: u64,
+	pub evictions: u64,
+	/// Values from the disk cache the worker has not confirmed yet
+	pub stale: usize,
+}
+
+fn string_size(x: &str) -> usize {
+	size_of::<String>() + x.len()
+}
+
+/// Rough number of bytes an entry takes up. The path is counted twice, as it is also the key
+/// of the recency map.
+pub fn entry_size(path: &BrowserPath, data: &PathData) -> usize {
+	let path_size = size_of::<BrowserPath>() + path.0.iter().map(|x| string_size(x)).sum::<usize>();
+	let data_size = match data {
+		PathData::List(x) => x.list.iter().map(|x| string_size(x)).sum(),
+		PathData::String(x) | PathData::Path(x) | PathData::Error(x) => x.len(),
+		_ => 0,
+	};
+	2 * path_size + size_of::<PathData>() + data_size + size_of::<u64>()
+}
+
+fn mib(bytes: usize) -> String {
+	format!("{:.1} MiB", bytes as f64 / (1024.0 * 1024.0))
+}
+
+/// Panel in the top right corner with the counters of the cache
+pub fn render_cache_stats(f: &mut Frame, stats: &CacheStats, outer: Rect) {
+	let lookups = stats.hits + stats.misses;
+	let hit_rate = match lookups {
+		0 => "-".to_string(),
+		n => format!("{}%", stats.hits * 100 / n),
+	};
+	let size = match stats.max_bytes {
+		Some(max) => format!("{} / {}", mib(stats.bytes), mib(max)),
+		None => mib(stats.bytes),
+	};
+	let rows = [
+		("Entries", stats.entries.to_string()),
+		("Size", size),
+		("Hits", stats.hits.to_string()),
+		("Misses", stats.misses.to_string()),
+		("Hit rate", hit_rate),
+		("Evicted", stats.evictions.to_string()),
+		("Stale", stats.stale.to_string()),
+	];
+	let lines = rows
+		.iter()
+		.map(|(name, value)| {
+			Line::from(vec![
+				Span::styled(format!("{name:9}"), theme().dim),
+				Span::styled(value.as_str(), theme().text),
+			])
+		})
+		.collect::<Vec<_>>();
+
+	let width = (lines.iter().map(|x| x.width()).max().unwrap_or(0) + 2)
+		.max(20)
+		.min(outer.width as usize) as u16;
+	let height = (lines.len() as u16 + 2).min(outer.height);
+	let rect = Rect::new(outer.x + outer.width - width, outer.y, width, height);
+	let block = Block::default()
+		.borders(Borders::ALL)
+		.title("Cache")
+		.title_style(theme().title);
+	Clear.render(rect, f.buffer_mut());
+	f.render_widget(Paragraph::new(lines).block(block), rect);
+}
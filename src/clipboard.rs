@@ -0,0 +1,71 @@
+use std::{
+	env,
+	io::{self, stdout, Write},
+	process::{Command, Stdio},
+};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+
+use crate::model::PathData;
+
+/// Escape sequence asking the terminal to put `text` on the clipboard. Inside tmux it has to be
+/// wrapped in a passthrough sequence to reach the outer terminal.
+pub fn osc52(text: &str, tmux: bool) -> String {
+	let sequence = format!("\x1b]52;c;{}\x07", STANDARD.encode(text));
+	if tmux {
+		format!("\x1bPtmux;\x1b{sequence}\x1b\\")
+	} else {
+		sequence
+	}
+}
+
+/// Copy `text` through OSC 52, which also works over SSH, and additionally hand it to `wl-copy`
+/// or `xclip` when running in a graphical session that has one of them
+pub fn copy(text: &str) -> io::Result<()> {
+	let mut out = stdout();
+	out.write_all(osc52(text, env::var_os("TMUX").is_some()).as_bytes())?;
+	out.flush()?;
+
+	let command: Option<&[&str]> = if env::var_os("WAYLAND_DISPLAY").is_some() {
+		Some(&["wl-copy"])
+	} else if env::var_os("DISPLAY").is_some() {
+		Some(&["xclip", "-selection", "clipboard"])
+	} else {
+		None
+	};
+	if let Some(command) = command {
+		let child = Command::new(command[0])
+			.args(&command[1..])
+			.stdin(Stdio::piped())
+			.stdout(Stdio::null())
+			.stderr(Stdio::null())
+			.spawn();
+		// Not having either of them installed is fine, the terminal got the text already
+		if let Ok(mut child) = child {
+			if let Some(mut stdin) = child.stdin.take() {
+				let _ = stdin.write_all(text.as_bytes());
+			}
+			std::thread::spawn(move || child.wait());
+		}
+	}
+	Ok(())
+}
+
+/// Text to copy for an evaluated leaf value. Attribute sets and lists are copied as JSON instead.
+pub fn leaf_text(data: &PathData) -> Option<String> {
+	match data {
+		PathData::String(x) | PathData::Path(x) => Some(x.clone()),
+		PathData::Int(x) => Some(x.to_string()),
+		PathData::Float(x) => Some(x.to_string()),
+		PathData::Bool(x) => Some(x.to_string()),
+		PathData::Null => Some("null".to_string()),
+		PathData::Error(x) => Some(x.clone()),
+		_ => None,
+	}
+}
+
+#[test]
+pub fn test_osc52() {
+	assert_eq!(osc52("hi", false), "\x1b]52;c;aGk=\x07");
+	assert_eq!(osc52("hi", true), "\x1bPtmux;\x1b\x1b]52;c;aGk=\x07\x1b\\");
+}
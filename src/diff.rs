@@ -0,0 +1,303 @@
+use std::collections::HashSet;
+
+use ratatui::style::Style;
+
+use crate::{
+	model::{BrowserPath, ListData, PathData, PathDataMap},
+	theme::theme,
+};
+
+/// Attribute sets with more children than this are not compared child by child, to avoid
+/// flooding the worker when diffing something like `pkgs`
+pub const MAX_DIFF_CHILDREN: usize = 512;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiffStatus {
+	Same,
+	Changed,
+	/// Only present on the right hand side
+	Added,
+	/// Only present on the left hand side
+	Removed,
+	/// One of the two sides has not been evaluated yet
+	Unknown,
+}
+
+impl DiffStatus {
+	pub fn is_difference(&self) -> bool {
+		matches!(
+			self,
+			DiffStatus::Changed | DiffStatus::Added | DiffStatus::Removed
+		)
+	}
+
+	pub fn marker(&self) -> &'static str {
+		match self {
+			DiffStatus::Same => "  ",
+			DiffStatus::Changed => "~ ",
+			DiffStatus::Added => "+ ",
+			DiffStatus::Removed => "- ",
+			DiffStatus::Unknown => "? ",
+		}
+	}
+
+	pub fn style(&self) -> Style {
+		let theme = theme();
+		match self {
+			DiffStatus::Same => Style::new(),
+			DiffStatus::Changed => theme.changed,
+			DiffStatus::Added => theme.added,
+			DiffStatus::Removed => theme.removed,
+			DiffStatus::Unknown => theme.dim,
+		}
+	}
+}
+
+/// Which side of a diff a value belongs to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DiffSide {
+	Left,
+	Right,
+}
+
+/// Two subtrees being walked in parallel. The visit stack always moves through the left hand
+/// side, and every path below `left` has a counterpart below `right`.
+#[derive(Debug, Default)]
+pub struct DiffState {
+	pub left: BrowserPath,
+	pub right: BrowserPath,
+	/// Values for the right hand side when it is evaluated from its own root expression,
+	/// otherwise both sides live in the model's `PathDataMap`
+	pub right_data: Option<PathDataMap>,
+	pub requested: HashSet<(DiffSide, BrowserPath)>,
+}
+
+/// Diff statuses of every item of a list, plus the names that only exist on the right hand side
+#[derive(Debug, Default)]
+pub struct ListDiff {
+	pub statuses: Vec<DiffStatus>,
+	pub added: Vec<String>,
+}
+
+impl DiffState {
+	pub fn new(left: BrowserPath, right: BrowserPath, separate_root: bool) -> DiffState {
+		DiffState {
+			left,
+			right,
+			right_data: separate_root.then(PathDataMap::default),
+			requested: HashSet::new(),
+		}
+	}
+
+	/// Allow requesting paths evicted from the model's `PathDataMap` again
+	pub fn forget(&mut self, evicted: &[BrowserPath]) {
+		let shared = self.right_data.is_none();
+		let evicted = evicted.iter().collect::<HashSet<_>>();
+		self.requested.retain(|(side, path)| {
+			(*side == DiffSide::Right && !shared) || !evicted.contains(path)
+		});
+	}
+
+	/// The path on the right hand side corresponding to a path below `left`
+	pub fn counterpart(&self, path: &BrowserPath) -> Option<BrowserPath> {
+		let rel = path.0.strip_prefix(self.left.0.as_slice())?;
+		Some(BrowserPath(
+			self.right.0.iter().chain(rel.iter()).cloned().collect(),
+		))
+	}
+
+	pub fn right_map<'a>(&'a self, path_data: &'a PathDataMap) -> &'a PathDataMap {
+		self.right_data.as_ref().unwrap_or(path_data)
+	}
+
+	/// Compare a single value on the left with its counterpart on the right
+	pub fn status(&self, path_data: &PathDataMap, left_path: &BrowserPath) -> DiffStatus {
+		let right_path = match self.counterpart(left_path) {
+			Some(x) => x,
+			None => return DiffStatus::Unknown,
+		};
+		let right_map = self.right_map(path_data);
+		if let Some(right_parent) = right_path.parent().and_then(|x| right_map.current_list(&x)) {
+			if !right_parent.list.contains(right_path.0.last().unwrap()) {
+				return DiffStatus::Removed;
+			}
+		}
+		let (left, right) = match (path_data.get(left_path), right_map.get(&right_path)) {
+			(Some(l), Some(r)) => (l, r),
+			_ => return DiffStatus::Unknown,
+		};
+		// Derivations are compared by their output path, which changes whenever anything they
+		// depend on does
+		if let (PathData::List(l), PathData::List(r)) = (left, right) {
+			if has_out_path(l) && has_out_path(r) {
+				let out_path = |map: &PathDataMap, p: &BrowserPath| {
+					map.get(&p.child("outPath".to_string())).cloned()
+				};
+				return match (
+					out_path(path_data, left_path),
+					out_path(right_map, &right_path),
+				) {
+					(Some(l), Some(r)) => compare(&l, &r),
+					_ => DiffStatus::Unknown,
+				};
+			}
+			// Sets with the same names are only the same once all their children are
+			if l.list != r.list {
+				return DiffStatus::Changed;
+			}
+			if l.list.len() > MAX_DIFF_CHILDREN {
+				return DiffStatus::Unknown;
+			}
+			let mut status = DiffStatus::Same;
+			for name in &l.list {
+				match self.status(path_data, &left_path.child(name.clone())) {
+					DiffStatus::Same => {}
+					DiffStatus::Unknown => status = DiffStatus::Unknown,
+					_ => return DiffStatus::Changed,
+				}
+			}
+			return status;
+		}
+		compare(left, right)
+	}
+
+	pub fn list_diff(&self, path_data: &PathDataMap, current: &BrowserPath) -> Option<ListDiff> {
+		let left = path_data.current_list(current)?;
+		let statuses = if left.list.len() > MAX_DIFF_CHILDREN {
+			vec![DiffStatus::Unknown; left.list.len()]
+		} else {
+			left.list
+				.iter()
+				.map(|x| self.status(path_data, &current.child(x.clone())))
+				.collect()
+		};
+		let added = self
+			.counterpart(current)
+			.and_then(|x| self.right_map(path_data).current_list(&x))
+			.map(|right| {
+				right
+					.list
+					.iter()
+					.filter(|x| !left.list.contains(x))
+					.cloned()
+					.collect()
+			})
+			.unwrap_or_default();
+		Some(ListDiff { statuses, added })
+	}
+
+	/// Paths that have to be evaluated on each side to diff the children of `current`
+	pub fn required_paths(
+		&self,
+		path_data: &PathDataMap,
+		current: &BrowserPath,
+	) -> Vec<(DiffSide, BrowserPath)> {
+		let mut required = vec![(DiffSide::Left, current.clone())];
+		let right_current = match self.counterpart(current) {
+			Some(x) => x,
+			None => return vec![],
+		};
+		required.push((DiffSide::Right, right_current.clone()));
+		if let Some(left) = path_data.current_list(current) {
+			if left.list.len() <= MAX_DIFF_CHILDREN {
+				for name in &left.list {
+					let left_child = current.child(name.clone());
+					let right_child = right_current.child(name.clone());
+					for (side, map, child) in [
+						(DiffSide::Left, path_data, &left_child),
+						(DiffSide::Right, self.right_map(path_data), &right_child),
+					] {
+						required.push((side, child.clone()));
+						if let Some(PathData::List(l)) = map.get(child) {
+							if has_out_path(l) {
+								required.push((side, child.child("outPath".to_string())));
+							}
+						}
+					}
+				}
+			}
+		}
+		let right_map = self.right_map(path_data);
+		required
+			.into_iter()
+			.filter(|(side, path)| {
+				let map = match side {
+					DiffSide::Left => path_data,
+					DiffSide::Right => right_map,
+				};
+				map.get(path).is_none() && !self.requested.contains(&(*side, path.clone()))
+			})
+			.collect()
+	}
+}
+
+fn has_out_path(list: &ListData) -> bool {
+	list.list.iter().any(|x| x == "outPath")
+}
+
+fn compare(left: &PathData, right: &PathData) -> DiffStatus {
+	let same = match (left, right) {
+		(PathData::Loading, _) | (_, PathData::Loading) => return DiffStatus::Unknown,
+		(PathData::Thunk, _) | (_, PathData::Thunk) => return DiffStatus::Unknown,
+		(PathData::Int(l), PathData::Int(r)) => l == r,
+		(PathData::Float(l), PathData::Float(r)) => l == r,
+		(PathData::Bool(l), PathData::Bool(r)) => l == r,
+		(PathData::String(l), PathData::String(r)) => l == r,
+		(PathData::Path(l), PathData::Path(r)) => l == r,
+		(PathData::Error(l), PathData::Error(r)) => l == r,
+		(PathData::Null, PathData::Null)
+		| (PathData::Function, PathData::Function)
+		| (PathData::External, PathData::External) => true,
+		_ => false,
+	};
+	if same {
+		DiffStatus::Same
+	} else {
+		DiffStatus::Changed
+	}
+}
+
+#[test]
+pub fn test_diff_status() {
+	use crate::model::attrs;
+
+	let path = |s: &str| BrowserPath::from(s.to_string());
+	let mut path_data = PathDataMap::default();
+	path_data.insert(path(".hosts.a"), attrs(&["x", "y"]));
+	path_data.insert(path(".hosts.b"), attrs(&["x", "z"]));
+	path_data.insert(path(".hosts.a.x"), PathData::Int(1));
+	path_data.insert(path(".hosts.b.x"), PathData::Int(2));
+
+	let diff = DiffState::new(path(".hosts.a"), path(".hosts.b"), false);
+	assert_eq!(
+		diff.counterpart(&path(".hosts.a.x")),
+		Some(path(".hosts.b.x"))
+	);
+	assert_eq!(diff.counterpart(&path(".hosts.c")), None);
+
+	let list_diff = diff.list_diff(&path_data, &path(".hosts.a")).unwrap();
+	assert_eq!(
+		list_diff.statuses,
+		vec![DiffStatus::Changed, DiffStatus::Removed]
+	);
+	assert_eq!(list_diff.added, vec!["z".to_string()]);
+
+	// Sets with the same names differ when a child does, and are unknown until it is evaluated
+	path_data.insert(path(".hosts.a.x"), attrs(&["n"]));
+	path_data.insert(path(".hosts.b.x"), attrs(&["n"]));
+	assert_eq!(
+		diff.status(&path_data, &path(".hosts.a.x")),
+		DiffStatus::Unknown
+	);
+	path_data.insert(path(".hosts.a.x.n"), PathData::Int(1));
+	path_data.insert(path(".hosts.b.x.n"), PathData::Int(2));
+	assert_eq!(
+		diff.status(&path_data, &path(".hosts.a.x")),
+		DiffStatus::Changed
+	);
+	path_data.insert(path(".hosts.b.x.n"), PathData::Int(1));
+	assert_eq!(
+		diff.status(&path_data, &path(".hosts.a.x")),
+		DiffStatus::Same
+	);
+}
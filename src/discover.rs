@@ -0,0 +1,256 @@
+//! Hosts and users of the root expression, found by evaluating it and listed in the root menu
+//! below the fixed entries
+
+use crate::model::{BrowserPath, PathDataMap};
+
+/// Sets of configurations in a flake, with the label shown next to their entries
+pub const CONFIGURATIONS: &[(&str, &str)] = &[
+	("nixosConfigurations", "nixos"),
+	("darwinConfigurations", "darwin"),
+	("homeConfigurations", "home"),
+];
+
+/// A configuration or home-manager user of the root expression
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RootEntry {
+	/// Label of where it was found, like `nixos` or `user`
+	pub kind: &'static str,
+	pub name: String,
+	pub path: BrowserPath,
+}
+
+fn root() -> BrowserPath {
+	BrowserPath::from(String::new())
+}
+
+fn users_path(config: &BrowserPath) -> BrowserPath {
+	config
+		.child("home-manager".to_string())
+		.child("users".to_string())
+}
+
+/// The `config` of every system found so far with the name of its host. A configuration that is
+/// not a flake is a single system without a name.
+fn systems(path_data: &PathDataMap) -> Vec<(Option<String>, BrowserPath)> {
+	let Some(root_list) = path_data.current_list(&root()) else {
+		return vec![];
+	};
+	if root_list.list.iter().any(|x| x == "config") {
+		return vec![(None, root().child("config".to_string()))];
+	}
+	let mut systems = vec![];
+	for set in ["nixosConfigurations", "darwinConfigurations"] {
+		let set_path = root().child(set.to_string());
+		if let Some(hosts) = path_data.current_list(&set_path) {
+			for host in &hosts.list {
+				let config = set_path.child(host.clone()).child("config".to_string());
+				systems.push((Some(host.clone()), config));
+			}
+		}
+	}
+	systems
+}
+
+/// What has to be evaluated to find the systems. Their home-manager users are looked up
+/// afterwards, see [`next_users_to_discover`], as that evaluates a whole config.
+pub fn discovery_paths(path_data: &PathDataMap) -> Vec<BrowserPath> {
+	let mut paths = vec![root()];
+	if let Some(root_list) = path_data.current_list(&root()) {
+		paths.extend(
+			CONFIGURATIONS
+				.iter()
+				.filter(|(set, _)| root_list.list.iter().any(|x| x == set))
+				.map(|(set, _)| root().child(set.to_string())),
+		);
+	}
+	paths
+}
+
+/// Discovery paths and the home-manager users that have been looked up, which are kept around
+/// so the entries don't disappear
+pub fn discovered_paths(path_data: &PathDataMap) -> Vec<BrowserPath> {
+	let mut paths = discovery_paths(path_data);
+	paths.extend(
+		systems(path_data)
+			.iter()
+			.map(|(_, config)| users_path(config))
+			.filter(|x| path_data.contains_key(x)),
+	);
+	paths
+}
+
+/// The next home-manager users to look up in the background, one system after the other so
+/// the values the user asks for are not stuck behind every config of the flake
+pub fn next_users_to_discover(path_data: &PathDataMap) -> Option<BrowserPath> {
+	systems(path_data)
+		.iter()
+		.map(|(_, config)| users_path(config))
+		.find(|x| !path_data.contains_key(x) || path_data.needs_confirmation(x))
+}
+
+/// The home-manager users to look up right away when `entry` is selected in the root menu,
+/// ahead of the ones discovered in the background. For a
+/// configuration that is not a flake, the root entry stands for the single system.
+pub fn users_to_discover(
+	path_data: &PathDataMap,
+	entry: Option<&RootEntry>,
+) -> Option<BrowserPath> {
+	match entry {
+		Some(entry) if matches!(entry.kind, "nixos" | "darwin") => {
+			Some(users_path(&entry.path.child("config".to_string())))
+		}
+		Some(_) => None,
+		None => match systems(path_data).as_slice() {
+			[(None, config)] => Some(users_path(config)),
+			_ => None,
+		},
+	}
+}
+
+/// Entries found so far, each system followed by its home-manager users
+pub fn root_entries(path_data: &PathDataMap) -> Vec<RootEntry> {
+	let users = |config: &BrowserPath, host: Option<&String>| {
+		let path = users_path(config);
+		let names = path_data
+			.current_list(&path)
+			.map(|x| x.list.clone())
+			.unwrap_or_default();
+		names
+			.into_iter()
+			.map(|user| RootEntry {
+				kind: "user",
+				name: match host {
+					Some(host) => format!("{user}@{host}"),
+					None => user.clone(),
+				},
+				path: path.child(user),
+			})
+			.collect::<Vec<_>>()
+	};
+
+	let mut entries = vec![];
+	for (set, kind) in CONFIGURATIONS {
+		let set_path = root().child(set.to_string());
+		let Some(list) = path_data.current_list(&set_path) else {
+			continue;
+		};
+		for name in &list.list {
+			let path = set_path.child(name.clone());
+			let config = path.child("config".to_string());
+			entries.push(RootEntry {
+				kind,
+				name: name.clone(),
+				path,
+			});
+			if *set != "homeConfigurations" {
+				entries.extend(users(&config, Some(name)));
+			}
+		}
+	}
+	if let [(None, config)] = systems(path_data).as_slice() {
+		entries.extend(users(config, None));
+	}
+	entries
+}
+
+#[test]
+pub fn test_root_entries() {
+	use crate::model::{attrs, PathData};
+
+	let path = |x: &str| BrowserPath::from(x.to_string());
+	let mut path_data = PathDataMap::default();
+	assert_eq!(discovery_paths(&path_data), vec![path("")]);
+
+	path_data.insert(
+		path(""),
+		attrs(&["homeConfigurations", "nixosConfigurations", "packages"]),
+	);
+	assert_eq!(
+		discovery_paths(&path_data),
+		vec![
+			path(""),
+			path(".nixosConfigurations"),
+			path(".homeConfigurations")
+		]
+	);
+
+	path_data.insert(path(".nixosConfigurations"), attrs(&["laptop", "server"]));
+	path_data.insert(path(".homeConfigurations"), attrs(&["bob"]));
+	assert_eq!(discovery_paths(&path_data).len(), 3);
+	assert_eq!(
+		next_users_to_discover(&path_data),
+		Some(path(
+			".nixosConfigurations.laptop.config.home-manager.users"
+		))
+	);
+	let laptop = root_entries(&path_data).into_iter().next();
+	assert_eq!(
+		users_to_discover(&path_data, laptop.as_ref()),
+		Some(path(
+			".nixosConfigurations.laptop.config.home-manager.users"
+		))
+	);
+	path_data.insert(
+		path(".nixosConfigurations.laptop.config.home-manager.users"),
+		attrs(&["alice"]),
+	);
+	assert_eq!(
+		next_users_to_discover(&path_data),
+		Some(path(
+			".nixosConfigurations.server.config.home-manager.users"
+		))
+	);
+	path_data.insert(
+		path(".nixosConfigurations.server.config.home-manager.users"),
+		PathData::Error("attribute 'home-manager' missing".to_string()),
+	);
+	assert_eq!(next_users_to_discover(&path_data), None);
+	assert!(discovered_paths(&path_data).contains(&path(
+		".nixosConfigurations.server.config.home-manager.users"
+	)));
+	let entries = root_entries(&path_data)
+		.into_iter()
+		.map(|x| (x.kind, x.name, x.path.to_expr()))
+		.collect::<Vec<_>>();
+	assert_eq!(
+		entries,
+		vec![
+			(
+				"nixos",
+				"laptop".to_string(),
+				"nixosConfigurations.laptop".to_string()
+			),
+			(
+				"user",
+				"alice@laptop".to_string(),
+				"nixosConfigurations.laptop.config.home-manager.users.alice".to_string()
+			),
+			(
+				"nixos",
+				"server".to_string(),
+				"nixosConfigurations.server".to_string()
+			),
+			(
+				"home",
+				"bob".to_string(),
+				"homeConfigurations.bob".to_string()
+			),
+		]
+	);
+
+	// A configuration that is not a flake
+	let mut path_data = PathDataMap::default();
+	path_data.insert(path(""), attrs(&["config", "options"]));
+	assert_eq!(
+		users_to_discover(&path_data, None),
+		Some(path(".config.home-manager.users"))
+	);
+	path_data.insert(path(".config.home-manager.users"), attrs(&["alice"]));
+	assert_eq!(
+		root_entries(&path_data)
+			.into_iter()
+			.map(|x| x.name)
+			.collect::<Vec<_>>(),
+		vec!["alice"]
+	);
+}
@@ -0,0 +1,221 @@
+//! Evaluation results saved between runs, so the last known values can be shown while the
+//! worker is still evaluating. A cache file belongs to a root expression together with the
+//! locked inputs of its flake, or the modification times of its files when it is not a flake.
+
+use std::{
+	fs,
+	path::{Path, PathBuf},
+	time::UNIX_EPOCH,
+};
+
+use ratatui::widgets::ListState;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+	eval::is_eval_path,
+	logging::get_data_dir,
+	model::{BrowserPath, ListData, ListType, PathData, PathDataMap},
+	stable_hash::Fnv1a,
+};
+
+/// Cache files kept around, the least recently written ones are deleted
+const KEPT_FILES: usize = 10;
+
+/// `PathData` without the list cursor, as stored on disk
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[serde(tag = "type", content = "value", rename_all = "lowercase")]
+enum CachedData {
+	Attrset(Vec<String>),
+	List(Vec<String>),
+	Thunk,
+	Int(i64),
+	Float(f64),
+	Bool(bool),
+	String(String),
+	Path(String),
+	Null,
+	Function,
+	External,
+	Error(String),
+}
+
+impl CachedData {
+	fn new(data: &PathData) -> Option<CachedData> {
+		Some(match data {
+			PathData::List(x) => match x.list_type {
+				ListType::Attrset => CachedData::Attrset(x.list.clone()),
+				ListType::List => CachedData::List(x.list.clone()),
+			},
+			PathData::Thunk => CachedData::Thunk,
+			PathData::Int(x) => CachedData::Int(*x),
+			PathData::Float(x) => CachedData::Float(*x),
+			PathData::Bool(x) => CachedData::Bool(*x),
+			PathData::String(x) => CachedData::String(x.clone()),
+			PathData::Path(x) => CachedData::Path(x.clone()),
+			PathData::Null => CachedData::Null,
+			PathData::Function => CachedData::Function,
+			PathData::External => CachedData::External,
+			PathData::Error(x) => CachedData::Error(x.clone()),
+			PathData::Loading => return None,
+		})
+	}
+
+	fn into_data(self) -> PathData {
+		let list = |list_type, list| {
+			PathData::List(ListData {
+				state: ListState::default().with_selected(Some(0)),
+				list_type,
+				list,
+			})
+		};
+		match self {
+			CachedData::Attrset(x) => list(ListType::Attrset, x),
+			CachedData::List(x) => list(ListType::List, x),
+			CachedData::Thunk => PathData::Thunk,
+			CachedData::Int(x) => PathData::Int(x),
+			CachedData::Float(x) => PathData::Float(x),
+			CachedData::Bool(x) => PathData::Bool(x),
+			CachedData::String(x) => PathData::String(x),
+			CachedData::Path(x) => PathData::Path(x),
+			CachedData::Null => PathData::Null,
+			CachedData::Function => PathData::Function,
+			CachedData::External => PathData::External,
+			CachedData::Error(x) => PathData::Error(x),
+		}
+	}
+}
+
+/// Modification times of the files below `dir`, skipping hidden ones like `.git`
+fn hash_mtimes(dir: &Path, hasher: &mut Fnv1a) {
+	let Ok(entries) = fs::read_dir(dir) else {
+		return;
+	};
+	let mut entries = entries.filter_map(|x| x.ok()).collect::<Vec<_>>();
+	entries.sort_by_key(|x| x.file_name());
+	for entry in entries {
+		if entry.file_name().to_string_lossy().starts_with('.') {
+			continue;
+		}
+		let Ok(metadata) = entry.metadata() else {
+			continue;
+		};
+		if metadata.is_dir() {
+			hash_mtimes(&entry.path(), hasher);
+		} else {
+			let modified = metadata
+				.modified()
+				.ok()
+				.and_then(|x| x.duration_since(UNIX_EPOCH).ok())
+				.map_or(0, |x| x.as_nanos());
+			hasher.write_field(entry.path().as_os_str().as_encoded_bytes());
+			hasher.write_field(&modified.to_le_bytes());
+		}
+	}
+}
+
+/// Hash of the root expression and what its values depend on. `root` is the directory of the
+/// config, plain expressions have none.
+pub fn cache_key(expr: &str, root: Option<&Path>) -> u64 {
+	let mut hasher = Fnv1a::default();
+	hasher.write_field(expr.as_bytes());
+	if let Some(root) = root {
+		let lock = fs::read_to_string(root.join("flake.lock"))
+			.ok()
+			.and_then(|x| serde_json::from_str::<serde_json::Value>(&x).ok());
+		match lock {
+			Some(lock) => {
+				let mut hashes = lock["nodes"]
+					.as_object()
+					.into_iter()
+					.flatten()
+					.filter_map(|(_, node)| node["locked"]["narHash"].as_str())
+					.collect::<Vec<_>>();
+				hashes.sort_unstable();
+				for hash in hashes {
+					hasher.write_field(hash.as_bytes());
+				}
+			}
+			None => hash_mtimes(root, &mut hasher),
+		}
+	}
+	hasher.finish()
+}
+
+fn cache_dir() -> PathBuf {
+	get_data_dir().join("eval-cache")
+}
+
+fn cache_file(key: u64) -> PathBuf {
+	cache_dir().join(format!("{key:016x}.json"))
+}
+
+fn to_entries(path_data: &PathDataMap) -> Vec<(BrowserPath, CachedData)> {
+	path_data
+		.iter()
+		.filter(|(path, _)| !is_eval_path(path) && !path_data.is_stale(path))
+		.filter_map(|(path, data)| Some((path.clone(), CachedData::new(data)?)))
+		.collect()
+}
+
+/// Values saved for `key`, to be shown as stale until the worker confirms them
+pub fn load(key: u64) -> anyhow::Result<Vec<(BrowserPath, PathData)>> {
+	let entries: Vec<(BrowserPath, CachedData)> =
+		serde_json::from_str(&fs::read_to_string(cache_file(key))?)?;
+	Ok(entries
+		.into_iter()
+		.map(|(path, data)| (path, data.into_data()))
+		.collect())
+}
+
+/// Save every confirmed value of the root expression, then delete old cache files
+pub fn save(key: u64, path_data: &PathDataMap) -> anyhow::Result<()> {
+	fs::create_dir_all(cache_dir())?;
+	fs::write(
+		cache_file(key),
+		serde_json::to_string(&to_entries(path_data))?,
+	)?;
+
+	let mut files = fs::read_dir(cache_dir())?
+		.filter_map(|x| x.ok())
+		.filter_map(|x| Some((x.metadata().ok()?.modified().ok()?, x.path())))
+		.collect::<Vec<_>>();
+	files.sort_unstable_by_key(|(modified, _)| std::cmp::Reverse(*modified));
+	// The new cache is written already, an old file that can't be removed is tried again next time
+	for (_, file) in files.into_iter().skip(KEPT_FILES) {
+		if let Err(e) = fs::remove_file(&file) {
+			tracing::warn!("removing old cache file {} failed: {e}", file.display());
+		}
+	}
+	Ok(())
+}
+
+#[test]
+pub fn test_cached_data() {
+	use crate::model::attrs;
+
+	let path = |x: &str| BrowserPath::from(x.to_string());
+	let mut path_data = PathDataMap::default();
+	path_data.insert(path(""), attrs(&["a b", "c"]));
+	path_data.insert(path(r#"."a b""#), PathData::String("x".to_string()));
+	path_data.insert(path(".c"), PathData::Loading);
+	path_data.insert(path(":1 + 1"), PathData::Int(2));
+	path_data.insert_stale(path(".d"), PathData::Int(3));
+
+	let mut entries = to_entries(&path_data);
+	entries.sort_by_key(|(path, _)| path.to_string());
+	let expected = vec![
+		(
+			path(""),
+			CachedData::Attrset(vec!["a b".to_string(), "c".to_string()]),
+		),
+		(path(r#"."a b""#), CachedData::String("x".to_string())),
+	];
+	assert_eq!(entries, expected);
+	let json = serde_json::to_string(&entries).unwrap();
+	assert!(json.contains(r#"{"type":"string","value":"x"}"#));
+	let loaded: Vec<(BrowserPath, CachedData)> = serde_json::from_str(&json).unwrap();
+	assert_eq!(loaded, expected);
+
+	assert_eq!(cache_key("x", None), cache_key("x", None));
+	assert_ne!(cache_key("x", None), cache_key("y", None));
+}
@@ -0,0 +1,153 @@
+use crate::model::{BrowserPath, ListType, PathDataMap};
+
+/// First character of the first element of every path holding an evaluation result, which keeps
+/// them apart from the values of the root expression in the `PathDataMap`
+pub const EVAL_PREFIX: char = ':';
+
+/// An expression typed into the `:` prompt, evaluated by its own worker. Its results are
+/// browsable below `root`.
+pub struct EvalSession {
+	/// Single element path of the result, e.g. `[":builtins.attrNames _"]`
+	pub root: BrowserPath,
+	/// Path in the root expression that `lib` and `pkgs` are looked up from
+	pub origin: BrowserPath,
+	/// Root expression of the worker
+	pub expr: String,
+	pub tx: kanal::Sender<BrowserPath>,
+}
+
+impl EvalSession {
+	/// Whether `path` is the result of this session or somewhere below it
+	pub fn contains(&self, path: &BrowserPath) -> bool {
+		path.0.first() == self.root.0.first()
+	}
+
+	/// The path to request from the session's worker, relative to its root expression
+	pub fn worker_path(&self, path: &BrowserPath) -> BrowserPath {
+		let mut path = path.clone();
+		path.0[0] = String::new();
+		path
+	}
+}
+
+pub fn is_eval_path(path: &BrowserPath) -> bool {
+	path.0.first().is_some_and(|x| x.starts_with(EVAL_PREFIX))
+}
+
+/// A nix string literal
+pub fn nix_string(s: &str) -> String {
+	let escaped = s
+		.replace('\\', "\\\\")
+		.replace('"', "\\\"")
+		.replace("${", "\\${")
+		.replace('\n', "\\n");
+	format!("\"{escaped}\"")
+}
+
+/// Expression selecting `path` (minus its first element) from `base`. Lists are indexed with
+/// `builtins.elemAt`, since `.0` is not valid nix.
+pub fn select_expr(base: &str, path: &BrowserPath, path_data: &PathDataMap) -> String {
+	let mut expr = format!("({base})");
+	for i in 1..path.0.len() {
+		let parent = BrowserPath(path.0[..i].to_vec());
+		let is_list = path_data
+			.current_list(&parent)
+			.is_some_and(|x| x.list_type == ListType::List);
+		expr = if is_list {
+			format!("(builtins.elemAt {expr} {})", path.0[i])
+		} else {
+			format!("{expr}.{}", nix_string(&path.0[i]))
+		};
+	}
+	expr
+}
+
+/// `lib` and `pkgs` from the closest level of `origin` that has them, such as a NixOS system or
+/// its `config`, falling back to the flake's nixpkgs input and then `<nixpkgs>`
+fn scope_exprs(origin: &BrowserPath, path_data: &PathDataMap) -> (String, String) {
+	let levels: Vec<String> = (1..=origin.0.len())
+		.rev()
+		.map(|i| select_expr("root", &BrowserPath(origin.0[..i].to_vec()), path_data))
+		.collect();
+	let lib = levels
+		.iter()
+		.flat_map(|x| [format!("{x}.lib"), format!("{x}._module.args.pkgs.lib")])
+		.chain([
+			"root.inputs.nixpkgs.lib".to_string(),
+			"(import <nixpkgs/lib>)".to_string(),
+		]);
+	let pkgs = levels
+		.iter()
+		.flat_map(|x| [format!("{x}.pkgs"), format!("{x}._module.args.pkgs")])
+		.chain([
+			"root.inputs.nixpkgs.legacyPackages.${builtins.currentSystem}".to_string(),
+			"(import <nixpkgs> { })".to_string(),
+		]);
+	(
+		lib.collect::<Vec<_>>().join(" or "),
+		pkgs.collect::<Vec<_>>().join(" or "),
+	)
+}
+
+/// Root expression evaluating `input` with `_` bound to `value`, and `root`, `lib` and `pkgs` in
+/// scope. It has to stay on one line, as that is how it is sent to the worker.
+pub fn eval_expr(
+	root_expr: &str,
+	value: &str,
+	origin: &BrowserPath,
+	path_data: &PathDataMap,
+	input: &str,
+) -> String {
+	let (lib, pkgs) = scope_exprs(origin, path_data);
+	format!("let root = ({root_expr}); _ = {value}; lib = {lib}; pkgs = {pkgs}; in ({input})")
+}
+
+/// File for `nix repl --file`, bringing the attributes of the root expression into scope like
+/// `:lf` does, along with `_`, `root`, `lib` and `pkgs`
+pub fn repl_expr(
+	root_expr: &str,
+	value: &str,
+	origin: &BrowserPath,
+	path_data: &PathDataMap,
+) -> String {
+	let (lib, pkgs) = scope_exprs(origin, path_data);
+	format!(
+		r#"let
+  root = ({root_expr});
+in
+(if builtins.isAttrs root then root else {{ }}) // {{
+  inherit root;
+  _ = {value};
+  lib = {lib};
+  pkgs = {pkgs};
+}}
+"#
+	)
+}
+
+#[test]
+pub fn test_select_expr() {
+	use crate::model::{ListData, PathData};
+	use ratatui::widgets::ListState;
+
+	let mut path_data = PathDataMap::default();
+	path_data.insert(
+		BrowserPath::from(".a.list".to_string()),
+		PathData::List(ListData {
+			state: ListState::default(),
+			list_type: ListType::List,
+			list: vec!["0".to_string()],
+		}),
+	);
+	let path = BrowserPath(
+		["", "a", "list", "0", "x.y"]
+			.iter()
+			.map(|x| x.to_string())
+			.collect(),
+	);
+	assert_eq!(
+		select_expr("root", &path, &path_data),
+		r#"(builtins.elemAt (root)."a"."list" 0)."x.y""#
+	);
+	assert_eq!(nix_string("a\"${b}"), r#""a\"\${b}""#);
+}
@@ -0,0 +1,278 @@
+use std::{
+	collections::HashMap,
+	fs,
+	io::Read,
+	ops::{Deref, DerefMut},
+	path::{Path, PathBuf},
+};
+
+use ratatui::{
+	layout::Rect,
+	style::Style,
+	text::Line,
+	widgets::{Paragraph, Wrap},
+	Frame,
+};
+
+use crate::{
+	model::BrowserPath,
+	string_view::{line_count, render_string_view, Syntax},
+	theme::theme,
+};
+
+/// Text files are cut off after this many lines
+const MAX_PREVIEW_LINES: usize = 2000;
+/// Only this many bytes are read from a file, so huge store paths don't stall the UI
+const MAX_PREVIEW_BYTES: u64 = 512 * 1024;
+
+pub const STORE_DIR: &str = "/nix/store/";
+
+#[derive(Debug, Clone)]
+pub struct FilePreview {
+	pub path: PathBuf,
+	/// Where the path points to if it is (or goes through) a symlink
+	pub resolved: Option<PathBuf>,
+	pub kind: FilePreviewKind,
+}
+
+#[derive(Debug, Clone)]
+pub enum FilePreviewKind {
+	Text {
+		content: String,
+		truncated: bool,
+	},
+	Directory(Vec<String>),
+	Binary(u64),
+	/// Still being read in the background
+	Loading,
+	/// A store path that has been evaluated but not built or substituted yet
+	NotRealised,
+	Missing,
+	Error(String),
+}
+
+impl FilePreview {
+	pub fn load(path: &str) -> FilePreview {
+		let path = PathBuf::from(path);
+		let resolved = fs::canonicalize(&path).ok().filter(|x| x != &path);
+		let kind = match fs::metadata(&path) {
+			Ok(meta) if meta.is_dir() => read_dir(&path),
+			Ok(meta) => read_file(&path, meta.len()),
+			Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+				if path.starts_with(STORE_DIR) {
+					FilePreviewKind::NotRealised
+				} else {
+					FilePreviewKind::Missing
+				}
+			}
+			Err(e) => FilePreviewKind::Error(e.to_string()),
+		};
+		FilePreview {
+			path,
+			resolved,
+			kind,
+		}
+	}
+
+	/// Placeholder shown until [`FilePreview::load`] is done
+	pub fn loading(path: &str) -> FilePreview {
+		FilePreview {
+			path: PathBuf::from(path),
+			resolved: None,
+			kind: FilePreviewKind::Loading,
+		}
+	}
+
+	/// Number of lines the preview can be scrolled through, if it is text or a directory
+	pub fn line_count(&self) -> Option<usize> {
+		match &self.kind {
+			FilePreviewKind::Text { content, .. } => Some(line_count(content)),
+			FilePreviewKind::Directory(entries) => Some(entries.len()),
+			_ => None,
+		}
+	}
+
+	pub fn text(&self) -> Option<&str> {
+		match &self.kind {
+			FilePreviewKind::Text { content, .. } => Some(content),
+			_ => None,
+		}
+	}
+
+	pub fn title(&self) -> String {
+		let kind = match &self.kind {
+			FilePreviewKind::Text {
+				truncated: true, ..
+			} => "file, truncated".to_string(),
+			FilePreviewKind::Text { .. } => "file".to_string(),
+			FilePreviewKind::Directory(entries) => format!("directory, {} entries", entries.len()),
+			FilePreviewKind::Binary(size) => format!("binary, {size} bytes"),
+			FilePreviewKind::Loading => "loading".to_string(),
+			FilePreviewKind::NotRealised => "not realised".to_string(),
+			FilePreviewKind::Missing => "missing".to_string(),
+			FilePreviewKind::Error(_) => "unreadable".to_string(),
+		};
+		match &self.resolved {
+			Some(resolved) => format!("{kind} -> {}", resolved.display()),
+			None => kind,
+		}
+	}
+}
+
+fn read_dir(path: &Path) -> FilePreviewKind {
+	let entries = match fs::read_dir(path) {
+		Ok(x) => x,
+		Err(e) => return FilePreviewKind::Error(e.to_string()),
+	};
+	let mut entries: Vec<String> = entries
+		.filter_map(|x| x.ok())
+		.map(|entry| {
+			let name = entry.file_name().to_string_lossy().to_string();
+			match entry.file_type() {
+				Ok(t) if t.is_symlink() => match fs::read_link(entry.path()) {
+					Ok(target) => format!("{name} -> {}", target.display()),
+					Err(_) => name,
+				},
+				Ok(t) if t.is_dir() => format!("{name}/"),
+				_ => name,
+			}
+		})
+		.collect();
+	entries.sort();
+	FilePreviewKind::Directory(entries)
+}
+
+fn read_file(path: &Path, size: u64) -> FilePreviewKind {
+	let mut bytes = Vec::new();
+	let read =
+		fs::File::open(path).and_then(|file| file.take(MAX_PREVIEW_BYTES).read_to_end(&mut bytes));
+	if let Err(e) = read {
+		return FilePreviewKind::Error(e.to_string());
+	}
+	if bytes.contains(&0) {
+		return FilePreviewKind::Binary(size);
+	}
+	let content = String::from_utf8_lossy(&bytes);
+	let mut truncated = size > MAX_PREVIEW_BYTES;
+	let content = match content.match_indices('\n').nth(MAX_PREVIEW_LINES - 1) {
+		Some((i, _)) => {
+			truncated = true;
+			content[..i].to_string()
+		}
+		None => content.to_string(),
+	};
+	FilePreviewKind::Text { content, truncated }
+}
+
+/// File previews for every path value that has been loaded so far, keyed by the path on disk
+#[derive(Default, Debug)]
+pub struct FilePreviews(HashMap<String, FilePreview>);
+
+impl Deref for FilePreviews {
+	type Target = HashMap<String, FilePreview>;
+
+	fn deref(&self) -> &Self::Target {
+		&self.0
+	}
+}
+impl DerefMut for FilePreviews {
+	fn deref_mut(&mut self) -> &mut Self::Target {
+		&mut self.0
+	}
+}
+
+pub fn render_file_preview(
+	f: &mut Frame,
+	path: &BrowserPath,
+	preview: &FilePreview,
+	scroll: usize,
+	search: Option<&str>,
+	inner: Rect,
+) {
+	let theme = theme();
+	let message =
+		|text: String, style: Style| Paragraph::new(text).style(style).wrap(Wrap { trim: true });
+	match &preview.kind {
+		FilePreviewKind::Text { content, .. } => {
+			// Guess from the file name first, falling back to the attribute the path came from
+			let file_name = preview
+				.path
+				.file_name()
+				.map(|x| x.to_string_lossy().to_string());
+			let syntax = match Syntax::guess(&path.child(file_name.unwrap_or_default()), content) {
+				Syntax::Plain => Syntax::guess(path, content),
+				x => x,
+			};
+			render_string_view(f, content, syntax, scroll, search, inner);
+		}
+		FilePreviewKind::Directory(entries) => {
+			let lines: Vec<Line> = entries
+				.iter()
+				.skip(scroll)
+				.map(|x| {
+					if x.ends_with('/') {
+						Line::styled(x.as_str(), theme.directory)
+					} else if x.contains(" -> ") {
+						Line::styled(x.as_str(), theme.symlink)
+					} else {
+						Line::raw(x.as_str())
+					}
+				})
+				.collect();
+			f.render_widget(Paragraph::new(lines), inner);
+		}
+		FilePreviewKind::Binary(size) => f.render_widget(
+			message(
+				format!("{}\n\nBinary file, {size} bytes", preview.path.display()),
+				theme.text,
+			),
+			inner,
+		),
+		FilePreviewKind::Loading => f.render_widget(
+			message(
+				format!("{}\n\nLoading ...", preview.path.display()),
+				theme.dim,
+			),
+			inner,
+		),
+		FilePreviewKind::NotRealised => f.render_widget(
+			message(
+				format!(
+					"{}\n\nNot realised: this store path has not been built or substituted yet",
+					preview.path.display()
+				),
+				theme.warning,
+			),
+			inner,
+		),
+		FilePreviewKind::Missing => f.render_widget(
+			message(
+				format!("{}\n\nPath does not exist", preview.path.display()),
+				theme.warning,
+			),
+			inner,
+		),
+		FilePreviewKind::Error(e) => f.render_widget(
+			message(format!("{}\n\n{e}", preview.path.display()), theme.error),
+			inner,
+		),
+	}
+}
+
+#[test]
+pub fn test_file_preview_kinds() {
+	let dir = std::env::temp_dir().join(format!("nix-inspect-preview-{}", std::process::id()));
+	fs::create_dir_all(&dir).unwrap();
+	fs::write(dir.join("config.sh"), "echo hi\n").unwrap();
+
+	let preview = FilePreview::load(dir.to_str().unwrap());
+	assert!(matches!(preview.kind, FilePreviewKind::Directory(ref x) if x == &["config.sh"]));
+	// Directories scroll like text
+	assert_eq!(preview.line_count(), Some(1));
+	let preview = FilePreview::load(dir.join("config.sh").to_str().unwrap());
+	assert_eq!(preview.text(), Some("echo hi\n"));
+	let preview = FilePreview::load("/nix/store/00000000000000000000000000000000-missing");
+	assert!(matches!(preview.kind, FilePreviewKind::NotRealised));
+
+	fs::remove_dir_all(&dir).unwrap();
+}
@@ -1,96 +1,218 @@
-use std::time::Duration;
+use std::{
+	sync::atomic::{AtomicBool, Ordering},
+	time::Duration,
+};
 
-use crossterm::event::{self, KeyCode, KeyModifiers};
+use std::fmt;
 
-use crate::model::{InputModel, InputState, Message, Model};
+use crossterm::event::{self, Event, KeyCode, MouseEvent, MouseEventKind};
+
+use crate::{
+	keymap::{InputMode, KeyChord},
+	model::{InputState, Message, Model},
+};
+
+/// Set while another program has the terminal, so its input is not stolen
+pub static KEY_HANDLER_PAUSED: AtomicBool = AtomicBool::new(false);
 
 pub fn register_key_handler(tx: &kanal::Sender<Message>) {
 	let tx = tx.clone();
 	std::thread::spawn(move || -> anyhow::Result<()> {
 		loop {
+			if KEY_HANDLER_PAUSED.load(Ordering::SeqCst) {
+				std::thread::sleep(Duration::from_millis(100));
+				continue;
+			}
 			if let Ok(true) = event::poll(Duration::from_millis(100)) {
-				let _ = tx.send(Message::TermEvent(event::read()?));
+				match event::read()? {
+					// Nothing reacts to the mouse just moving, so don't redraw for it
+					Event::Mouse(MouseEvent {
+						kind: MouseEventKind::Moved,
+						..
+					}) => {}
+					event => {
+						let _ = tx.send(Message::TermEvent(event));
+					}
+				}
 			}
 		}
 	});
 }
 
-pub fn handle_key(key: event::KeyEvent, model: &Model) -> Option<Message> {
-	if let InputState::Active(state) = &model.search_input {
-		handle_search_input(state, key)
+/// The input that currently receives key presses, and whether it is taking text
+pub fn input_mode(model: &Model) -> (InputMode, bool) {
+	if model.help.is_some() {
+		(InputMode::Help, false)
+	} else if model.batch.is_some() {
+		(InputMode::Batch, false)
+	} else if model.tree.is_some() {
+		(InputMode::Tree, false)
+	} else if let InputState::Active(state) = &model.search_input {
+		(InputMode::Search, state.typing)
 	} else if let InputState::Active(state) = &model.path_navigator_input {
-		handle_navigator_input(state, key)
-	} else if let InputState::Active(state) = &model.new_bookmark_input {
-		handle_bookmark_input(state, key)
+		(InputMode::Navigator, state.typing)
+	} else if let InputState::Active(_) = &model.new_bookmark_input {
+		(InputMode::BookmarkInput, true)
+	} else if let InputState::Active(_) = &model.eval_input {
+		(InputMode::EvalInput, true)
 	} else {
-		handle_normal_input(key)
+		(InputMode::Normal, false)
+	}
+}
+
+/// Keys typed so far of a multi-key command like `5j`, `gg` or `ma`
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct PendingKeys {
+	pub count: Option<usize>,
+	/// First key of a two key command
+	pub prefix: Option<char>,
+}
+
+impl PendingKeys {
+	pub fn is_empty(&self) -> bool {
+		self.count.is_none() && self.prefix.is_none()
 	}
 }
 
-fn handle_search_input(state: &InputModel, key: event::KeyEvent) -> Option<Message> {
-	if !state.typing {
-		match key.code {
-			KeyCode::Char('n') => Some(Message::SearchNext),
-			KeyCode::Char('N') => Some(Message::SearchPrev),
-			KeyCode::Esc => Some(Message::SearchExit),
-			_ => None,
+impl fmt::Display for PendingKeys {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		if let Some(count) = self.count {
+			write!(f, "{count}")?;
 		}
-	} else {
-		match key.code {
-			KeyCode::Esc => Some(Message::SearchExit),
-			_ => Some(Message::SearchInput(key)),
+		if let Some(prefix) = self.prefix {
+			write!(f, "{prefix}")?;
 		}
+		Ok(())
 	}
 }
 
-pub fn handle_bookmark_input(_: &InputModel, key: event::KeyEvent) -> Option<Message> {
-	match key.code {
-		KeyCode::Esc => Some(Message::BookmarkInputExit),
-		KeyCode::Enter => Some(Message::CreateBookmark),
-		_ => Some(Message::BookmarkInput(key)),
+/// Keys that start or finish vim style commands in normal mode, and can't be bound in the keymap
+pub const VIM_KEYS: &str = "0123456789gGzm'";
+
+/// Counts, `gg`, `G`, `zz` and marks. Returns None for keys that are left to the keymap.
+fn handle_vim_keys(pending: &PendingKeys, chord: &KeyChord) -> Option<Message> {
+	let c = match chord.code {
+		KeyCode::Char(c) if chord.modifiers.is_empty() => Some(c),
+		_ => None,
+	};
+	if let Some(prefix) = pending.prefix {
+		let count = pending.count.unwrap_or(1);
+		return Some(match (prefix, c) {
+			('g', Some('g')) => Message::ListGoto(count - 1),
+			('z', Some('z')) => Message::CenterCursor,
+			('m', Some(c)) if c.is_ascii_lowercase() => Message::SetMark(c),
+			('\'', Some(c)) if c.is_ascii_lowercase() => Message::JumpMark(c),
+			// Anything else cancels the command
+			_ => Message::PendingKeys(PendingKeys::default()),
+		});
+	}
+	match c? {
+		c @ '0'..='9' if c != '0' || pending.count.is_some() => {
+			let digit = c.to_digit(10).unwrap() as usize;
+			let count = pending.count.unwrap_or(0).saturating_mul(10);
+			Some(Message::PendingKeys(PendingKeys {
+				count: Some(count.saturating_add(digit).min(MAX_COUNT)),
+				prefix: None,
+			}))
+		}
+		c @ ('g' | 'z' | 'm' | '\'') => Some(Message::PendingKeys(PendingKeys {
+			count: pending.count,
+			prefix: Some(c),
+		})),
+		// Like in vim, `5G` goes to the fifth item
+		'G' => Some(Message::ListGoto(
+			pending.count.map(|x| x - 1).unwrap_or(usize::MAX),
+		)),
+		_ => None,
 	}
 }
 
-pub fn handle_navigator_input(state: &InputModel, key: event::KeyEvent) -> Option<Message> {
-	if !state.typing {
-		match key.code {
-			KeyCode::Char('n') => Some(Message::NavigatorNext),
-			KeyCode::Char('N') => Some(Message::NavigatorPrev),
-			KeyCode::Esc => Some(Message::NavigatorExit),
-			_ => Some(Message::NavigatorInput(key)),
-		}
-	} else {
-		match key.code {
-			KeyCode::Esc => Some(Message::NavigatorExit),
-			_ => Some(Message::NavigatorInput(key)),
-		}
+/// Keys of each mode that are handled outside the keymap and can't be changed, for the help
+pub fn fixed_keys(mode: InputMode) -> &'static [(&'static str, &'static str)] {
+	match mode {
+		InputMode::Normal => &[
+			("5j, 3k, ...", "Repeat a motion"),
+			("gg / G / 5G", "First / last / fifth item"),
+			("zz", "Center the cursor"),
+			("m{a-z}", "Set a mark"),
+			("'{a-z}", "Jump to a mark"),
+		],
+		InputMode::Search => &[("<Enter>", "Stop typing, to use n / N")],
+		InputMode::Navigator => &[
+			("<Tab> / <BackTab>", "Complete the next / previous match"),
+			("<Enter>", "Stop typing, to use n / N"),
+		],
+		InputMode::BookmarkInput
+		| InputMode::EvalInput
+		| InputMode::Tree
+		| InputMode::Batch
+		| InputMode::Help => &[],
 	}
 }
 
-pub fn handle_normal_input(key: event::KeyEvent) -> Option<Message> {
-	match key.code {
-		KeyCode::Char('q') => Some(Message::Quit),
-		KeyCode::Char('h') | KeyCode::Left => Some(Message::Back),
-		KeyCode::Char('j') | KeyCode::Down => Some(Message::ListDown),
-		KeyCode::Char('k') | KeyCode::Up => Some(Message::ListUp),
-		KeyCode::Char('l') | KeyCode::Right => Some(Message::EnterItem),
-		KeyCode::Char('f') | KeyCode::Char('/') => Some(Message::SearchEnter),
-		KeyCode::Char('s') => Some(Message::BookmarkInputEnter),
-		KeyCode::Char('r') => Some(Message::Refresh),
-		KeyCode::Char('d') => {
-			if key.modifiers.contains(KeyModifiers::CONTROL) {
-				Some(Message::PageDown)
-			} else {
-				Some(Message::DeleteBookmark)
-			}
+/// Counts are capped so holding down a digit doesn't freeze the app
+const MAX_COUNT: usize = 9999;
+
+pub fn handle_key(key: event::KeyEvent, model: &Model) -> Option<Message> {
+	let (mode, typing) = input_mode(model);
+	let chord = KeyChord::from(key);
+	if mode == InputMode::Normal {
+		if let Some(msg) = handle_vim_keys(&model.pending_keys, &chord) {
+			return Some(msg);
 		}
-		KeyCode::Char('u') => {
-			if key.modifiers.contains(KeyModifiers::CONTROL) {
-				return Some(Message::PageUp);
-			}
-			None
+	}
+	// Characters typed into an input are never looked up, so bindings like `n` don't get in the way
+	if !(typing && chord.is_text()) {
+		if let Some(action) = model.keymap.lookup(mode, &chord) {
+			return Some(match model.pending_keys.count {
+				Some(count) if action.repeatable() => Message::Repeat(count, action),
+				_ => action.message(),
+			});
 		}
-		KeyCode::Char('.') => Some(Message::NavigatorEnter),
-		_ => None,
 	}
+	match mode {
+		InputMode::Normal => None,
+		InputMode::Search => typing.then_some(Message::SearchInput(key)),
+		InputMode::Navigator => Some(Message::NavigatorInput(key)),
+		InputMode::BookmarkInput => Some(Message::BookmarkInput(key)),
+		InputMode::EvalInput => Some(Message::EvalInput(key)),
+		InputMode::Tree | InputMode::Batch | InputMode::Help => None,
+	}
+}
+
+#[test]
+pub fn test_vim_keys() {
+	let chord = |x: &str| x.parse::<KeyChord>().unwrap();
+	let pending = |count, prefix| PendingKeys { count, prefix };
+
+	let five = match handle_vim_keys(&PendingKeys::default(), &chord("5")) {
+		Some(Message::PendingKeys(x)) => x,
+		x => panic!("{:?}", x),
+	};
+	assert_eq!(five, pending(Some(5), None));
+	assert!(matches!(
+		handle_vim_keys(&five, &chord("0")),
+		Some(Message::PendingKeys(PendingKeys {
+			count: Some(50),
+			..
+		}))
+	));
+	assert!(handle_vim_keys(&PendingKeys::default(), &chord("0")).is_none());
+	assert!(matches!(
+		handle_vim_keys(&pending(Some(5), Some('g')), &chord("g")),
+		Some(Message::ListGoto(4))
+	));
+	assert!(matches!(
+		handle_vim_keys(&PendingKeys::default(), &chord("G")),
+		Some(Message::ListGoto(usize::MAX))
+	));
+	assert!(matches!(
+		handle_vim_keys(&pending(None, Some('m')), &chord("a")),
+		Some(Message::SetMark('a'))
+	));
+	assert!(matches!(
+		handle_vim_keys(&pending(None, Some('g')), &chord("<Esc>")),
+		Some(Message::PendingKeys(x)) if x.is_empty()
+	));
+	assert!(handle_vim_keys(&PendingKeys::default(), &chord("j")).is_none());
 }
@@ -0,0 +1,667 @@
+use std::{collections::BTreeMap, fmt, str::FromStr};
+
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use serde::Serialize;
+
+use crate::{bookmarks::BookmarkField, key_handler::VIM_KEYS, model::Message};
+
+/// Which input currently receives key presses
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum InputMode {
+	Normal,
+	Search,
+	Navigator,
+	BookmarkInput,
+	EvalInput,
+	Tree,
+	Batch,
+	Help,
+}
+
+impl InputMode {
+	pub const ALL: [InputMode; 8] = [
+		InputMode::Normal,
+		InputMode::Search,
+		InputMode::Navigator,
+		InputMode::BookmarkInput,
+		InputMode::EvalInput,
+		InputMode::Tree,
+		InputMode::Batch,
+		InputMode::Help,
+	];
+
+	/// The name of the mode as written in `config.json`
+	pub fn name(&self) -> &'static str {
+		match self {
+			InputMode::Normal => "normal",
+			InputMode::Search => "search",
+			InputMode::Navigator => "navigator",
+			InputMode::BookmarkInput => "bookmark_input",
+			InputMode::EvalInput => "eval_input",
+			InputMode::Tree => "tree",
+			InputMode::Batch => "batch",
+			InputMode::Help => "help",
+		}
+	}
+}
+
+/// Everything a key can be bound to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Action {
+	Quit,
+	Back,
+	ListDown,
+	ListUp,
+	EnterItem,
+	PageDown,
+	PageUp,
+	Search,
+	SearchNext,
+	SearchPrev,
+	SearchExit,
+	Navigator,
+	NavigatorNext,
+	NavigatorPrev,
+	NavigatorExit,
+	Bookmark,
+	CreateBookmark,
+	BookmarkExit,
+	DeleteBookmark,
+	ToggleBookmarkScope,
+	RenameBookmark,
+	EditBookmarkPath,
+	GroupBookmark,
+	BookmarkNote,
+	MoveBookmarkUp,
+	MoveBookmarkDown,
+	Refresh,
+	Reload,
+	DiffMark,
+	DiffNext,
+	DiffPrev,
+	SystemCompare,
+	Eval,
+	EvalConfirm,
+	EvalExit,
+	Repl,
+	CopyPath,
+	CopyValue,
+	Zoom,
+	CacheStats,
+	Sort,
+	SortAll,
+	Tree,
+	TreeDown,
+	TreeUp,
+	TreeExpand,
+	TreeCollapse,
+	BatchEval,
+	BatchDown,
+	BatchUp,
+	BatchEnter,
+	BatchExit,
+	Help,
+	HelpDown,
+	HelpUp,
+	HelpPageDown,
+	HelpPageUp,
+}
+
+impl Action {
+	pub fn message(&self) -> Message {
+		match self {
+			Action::Quit => Message::Quit,
+			Action::Back => Message::Back,
+			Action::ListDown => Message::ListDown,
+			Action::ListUp => Message::ListUp,
+			Action::EnterItem => Message::EnterItem,
+			Action::PageDown => Message::PageDown,
+			Action::PageUp => Message::PageUp,
+			Action::Search => Message::SearchEnter,
+			Action::SearchNext => Message::SearchNext,
+			Action::SearchPrev => Message::SearchPrev,
+			Action::SearchExit => Message::SearchExit,
+			Action::Navigator => Message::NavigatorEnter,
+			Action::NavigatorNext => Message::NavigatorNext,
+			Action::NavigatorPrev => Message::NavigatorPrev,
+			Action::NavigatorExit => Message::NavigatorExit,
+			Action::Bookmark => Message::BookmarkInputEnter,
+			Action::CreateBookmark => Message::CreateBookmark,
+			Action::BookmarkExit => Message::BookmarkInputExit,
+			Action::DeleteBookmark => Message::DeleteBookmark,
+			Action::ToggleBookmarkScope => Message::ToggleBookmarkScope,
+			Action::RenameBookmark => Message::EditBookmark(BookmarkField::Name),
+			Action::EditBookmarkPath => Message::EditBookmark(BookmarkField::Path),
+			Action::GroupBookmark => Message::EditBookmark(BookmarkField::Group),
+			Action::BookmarkNote => Message::EditBookmark(BookmarkField::Note),
+			Action::MoveBookmarkUp => Message::MoveBookmark(-1),
+			Action::MoveBookmarkDown => Message::MoveBookmark(1),
+			Action::Refresh => Message::Refresh,
+			Action::Reload => Message::Reload,
+			Action::DiffMark => Message::DiffMark,
+			Action::DiffNext => Message::DiffNext,
+			Action::DiffPrev => Message::DiffPrev,
+			Action::SystemCompare => Message::SystemCompareEnter,
+			Action::Eval => Message::EvalInputEnter,
+			Action::EvalConfirm => Message::Eval,
+			Action::EvalExit => Message::EvalInputExit,
+			Action::Repl => Message::Repl,
+			Action::CopyPath => Message::CopyPath,
+			Action::CopyValue => Message::CopyValue,
+			Action::Zoom => Message::Zoom,
+			Action::CacheStats => Message::CacheStats,
+			Action::Sort => Message::Sort,
+			Action::SortAll => Message::SortAll,
+			Action::Tree => Message::Tree,
+			Action::TreeDown => Message::TreeMove(1),
+			Action::TreeUp => Message::TreeMove(-1),
+			Action::TreeExpand => Message::TreeExpand,
+			Action::TreeCollapse => Message::TreeCollapse,
+			Action::BatchEval => Message::BatchEval,
+			Action::BatchDown => Message::BatchMove(1),
+			Action::BatchUp => Message::BatchMove(-1),
+			Action::BatchEnter => Message::BatchEnter,
+			Action::BatchExit => Message::BatchExit,
+			Action::Help => Message::Help,
+			Action::HelpDown => Message::HelpScroll(1),
+			Action::HelpUp => Message::HelpScroll(-1),
+			Action::HelpPageDown => Message::HelpPage(1),
+			Action::HelpPageUp => Message::HelpPage(-1),
+		}
+	}
+
+	/// Short description, as shown in the footer
+	pub fn label(&self) -> &'static str {
+		match self {
+			Action::Quit => "Quit",
+			Action::Back => "Back",
+			Action::ListDown | Action::HelpDown | Action::TreeDown | Action::BatchDown => "Down",
+			Action::ListUp | Action::HelpUp | Action::TreeUp | Action::BatchUp => "Up",
+			Action::EnterItem => "Enter",
+			Action::PageDown | Action::HelpPageDown => "Half-page down",
+			Action::PageUp | Action::HelpPageUp => "Half-page up",
+			Action::Search => "Find",
+			Action::SearchNext | Action::NavigatorNext => "Next Occurence",
+			Action::SearchPrev | Action::NavigatorPrev => "Previous Occurence",
+			Action::SearchExit => "Exit Search",
+			Action::Navigator => "Go To Path",
+			Action::NavigatorExit => "Exit Path Navigator",
+			Action::Bookmark => "Save Bookmark",
+			Action::CreateBookmark => "Save",
+			Action::BookmarkExit | Action::EvalExit => "Cancel",
+			Action::DeleteBookmark => "Delete Bookmark",
+			Action::ToggleBookmarkScope => "Toggle Global Bookmark",
+			Action::RenameBookmark => "Rename Bookmark",
+			Action::EditBookmarkPath => "Edit Bookmark Path",
+			Action::GroupBookmark => "Set Bookmark Group",
+			Action::BookmarkNote => "Edit Bookmark Note",
+			Action::MoveBookmarkUp => "Move Bookmark Up",
+			Action::MoveBookmarkDown => "Move Bookmark Down",
+			Action::Refresh => "Refresh",
+			Action::Reload => "Reload",
+			Action::DiffMark => "Diff",
+			Action::DiffNext => "Next Difference",
+			Action::DiffPrev => "Previous Difference",
+			Action::SystemCompare => "Compare System",
+			Action::Eval => "Eval",
+			Action::EvalConfirm => "Evaluate",
+			Action::Repl => "Repl",
+			Action::CopyPath => "Copy Path",
+			Action::CopyValue => "Copy Value",
+			Action::Zoom => "Zoom",
+			Action::CacheStats => "Cache Stats",
+			Action::Sort => "Sort",
+			Action::SortAll => "Sort All Lists",
+			Action::Tree => "Tree",
+			Action::TreeExpand => "Expand",
+			Action::TreeCollapse => "Collapse",
+			Action::BatchEval => "Evaluate All Children",
+			Action::BatchEnter => "Go To Child",
+			Action::BatchExit => "Close",
+			Action::Help => "Help",
+		}
+	}
+
+	/// Whether a count like the 5 in `5j` repeats the action
+	pub fn repeatable(&self) -> bool {
+		matches!(
+			self,
+			Action::Back
+				| Action::ListDown
+				| Action::ListUp
+				| Action::EnterItem
+				| Action::PageDown
+				| Action::PageUp
+				| Action::DiffNext
+				| Action::DiffPrev
+				| Action::MoveBookmarkUp
+				| Action::MoveBookmarkDown
+		)
+	}
+
+	/// Whether the action is worth the space in the footer
+	pub fn in_footer(&self) -> bool {
+		!matches!(
+			self,
+			Action::Back
+				| Action::ListDown
+				| Action::ListUp
+				| Action::EnterItem
+				| Action::DiffMark
+				| Action::DiffNext
+				| Action::DiffPrev
+				| Action::SystemCompare
+				| Action::Repl
+				| Action::CopyPath
+				| Action::CopyValue
+				| Action::Zoom
+				| Action::CacheStats
+				| Action::ToggleBookmarkScope
+				| Action::RenameBookmark
+				| Action::EditBookmarkPath
+				| Action::GroupBookmark
+				| Action::BookmarkNote
+				| Action::MoveBookmarkUp
+				| Action::MoveBookmarkDown
+				| Action::SortAll
+				| Action::TreeDown
+				| Action::TreeUp
+				| Action::BatchEval
+				| Action::BatchDown
+				| Action::BatchUp
+				| Action::HelpDown
+				| Action::HelpUp
+				| Action::HelpPageDown
+				| Action::HelpPageUp
+		)
+	}
+}
+
+/// Default bindings of each mode. An action can only be bound in the modes it is listed in here.
+pub fn default_bindings(mode: InputMode) -> &'static [(Action, &'static [&'static str])] {
+	match mode {
+		InputMode::Normal => &[
+			(Action::Navigator, &["."]),
+			(Action::Search, &["/", "f"]),
+			(Action::Eval, &[":"]),
+			(Action::Refresh, &["r"]),
+			(Action::Reload, &["R"]),
+			(Action::Bookmark, &["s"]),
+			(Action::DeleteBookmark, &["d"]),
+			(Action::ToggleBookmarkScope, &["S"]),
+			(Action::RenameBookmark, &["c"]),
+			(Action::EditBookmarkPath, &["e"]),
+			(Action::GroupBookmark, &["b"]),
+			(Action::BookmarkNote, &["i"]),
+			(Action::MoveBookmarkUp, &["K"]),
+			(Action::MoveBookmarkDown, &["J"]),
+			(Action::Quit, &["q"]),
+			(Action::PageDown, &["<C-d>"]),
+			(Action::PageUp, &["<C-u>"]),
+			(Action::Back, &["h", "<Left>"]),
+			(Action::ListDown, &["j", "<Down>"]),
+			(Action::ListUp, &["k", "<Up>"]),
+			(Action::EnterItem, &["l", "<Right>"]),
+			(Action::DiffMark, &["D"]),
+			(Action::DiffNext, &["]"]),
+			(Action::DiffPrev, &["["]),
+			(Action::SystemCompare, &["C"]),
+			(Action::Repl, &["!"]),
+			(Action::CopyPath, &["y"]),
+			(Action::CopyValue, &["Y"]),
+			(Action::Zoom, &["Z"]),
+			(Action::CacheStats, &["<F12>"]),
+			(Action::Sort, &["o"]),
+			(Action::SortAll, &["O"]),
+			(Action::BatchEval, &["E"]),
+			(Action::Tree, &["t"]),
+			(Action::Help, &["?", "<F1>"]),
+		],
+		InputMode::Search => &[
+			(Action::SearchNext, &["n"]),
+			(Action::SearchPrev, &["N"]),
+			(Action::SearchExit, &["<Esc>"]),
+			(Action::Help, &["<F1>"]),
+		],
+		InputMode::Navigator => &[
+			(Action::NavigatorNext, &["n"]),
+			(Action::NavigatorPrev, &["N"]),
+			(Action::NavigatorExit, &["<Esc>"]),
+			(Action::Help, &["<F1>"]),
+		],
+		InputMode::BookmarkInput => &[
+			(Action::CreateBookmark, &["<Enter>"]),
+			(Action::BookmarkExit, &["<Esc>"]),
+			(Action::Help, &["<F1>"]),
+		],
+		InputMode::EvalInput => &[
+			(Action::EvalConfirm, &["<Enter>"]),
+			(Action::EvalExit, &["<Esc>"]),
+			(Action::Help, &["<F1>"]),
+		],
+		InputMode::Tree => &[
+			(Action::Tree, &["t", "<Esc>"]),
+			(Action::Quit, &["q"]),
+			(Action::TreeDown, &["j", "<Down>"]),
+			(Action::TreeUp, &["k", "<Up>"]),
+			(Action::TreeExpand, &["l", "<Right>", "<Enter>"]),
+			(Action::TreeCollapse, &["h", "<Left>"]),
+			(Action::CopyPath, &["y"]),
+			(Action::CopyValue, &["Y"]),
+			(Action::Help, &["?", "<F1>"]),
+		],
+		InputMode::Batch => &[
+			(Action::BatchExit, &["q", "<Esc>", "E"]),
+			(Action::BatchDown, &["j", "<Down>"]),
+			(Action::BatchUp, &["k", "<Up>"]),
+			(Action::BatchEnter, &["l", "<Right>", "<Enter>"]),
+			(Action::Help, &["?", "<F1>"]),
+		],
+		InputMode::Help => &[
+			(Action::Help, &["?", "q", "<Esc>", "<F1>"]),
+			(Action::HelpDown, &["j", "<Down>"]),
+			(Action::HelpUp, &["k", "<Up>"]),
+			(Action::HelpPageDown, &["<C-d>", "<PageDown>"]),
+			(Action::HelpPageUp, &["<C-u>", "<PageUp>"]),
+		],
+	}
+}
+
+/// A key together with the modifiers held down. Written like `j`, `<Esc>` or `<C-d>`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct KeyChord {
+	pub code: KeyCode,
+	pub modifiers: KeyModifiers,
+}
+
+impl KeyChord {
+	/// Whether the chord types text into an input instead of triggering an action
+	pub fn is_text(&self) -> bool {
+		matches!(self.code, KeyCode::Char(_))
+			&& !self
+				.modifiers
+				.intersects(KeyModifiers::CONTROL | KeyModifiers::ALT)
+	}
+}
+
+impl From<KeyEvent> for KeyChord {
+	fn from(key: KeyEvent) -> Self {
+		let mut modifiers =
+			key.modifiers & (KeyModifiers::CONTROL | KeyModifiers::ALT | KeyModifiers::SHIFT);
+		// Shift is already part of the character, and implied by backtab
+		if matches!(key.code, KeyCode::Char(_) | KeyCode::BackTab) {
+			modifiers.remove(KeyModifiers::SHIFT);
+		}
+		KeyChord {
+			code: key.code,
+			modifiers,
+		}
+	}
+}
+
+const NAMED_KEYS: &[(&str, KeyCode)] = &[
+	("Esc", KeyCode::Esc),
+	("Enter", KeyCode::Enter),
+	("Tab", KeyCode::Tab),
+	("BackTab", KeyCode::BackTab),
+	("Backspace", KeyCode::Backspace),
+	("Delete", KeyCode::Delete),
+	("Insert", KeyCode::Insert),
+	("Left", KeyCode::Left),
+	("Right", KeyCode::Right),
+	("Up", KeyCode::Up),
+	("Down", KeyCode::Down),
+	("PageUp", KeyCode::PageUp),
+	("PageDown", KeyCode::PageDown),
+	("Home", KeyCode::Home),
+	("End", KeyCode::End),
+	("Space", KeyCode::Char(' ')),
+];
+
+impl FromStr for KeyChord {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		let mut chars = s.chars();
+		if let (Some(c), None) = (chars.next(), chars.next()) {
+			return Ok(KeyChord {
+				code: KeyCode::Char(c),
+				modifiers: KeyModifiers::NONE,
+			});
+		}
+		let mut inner = s
+			.strip_prefix('<')
+			.and_then(|x| x.strip_suffix('>'))
+			.ok_or_else(|| format!("invalid key {s:?}, expected a character or <...>"))?;
+		let mut modifiers = KeyModifiers::NONE;
+		let prefixes = [
+			("C-", KeyModifiers::CONTROL),
+			("A-", KeyModifiers::ALT),
+			("S-", KeyModifiers::SHIFT),
+		];
+		// A prefix needs a key after it, so `<C-->` is control and `-`
+		while let Some((rest, modifier)) = prefixes.iter().find_map(|(prefix, modifier)| {
+			let rest = inner.strip_prefix(prefix).filter(|x| !x.is_empty())?;
+			Some((rest, *modifier))
+		}) {
+			modifiers |= modifier;
+			inner = rest;
+		}
+		let mut chars = inner.chars();
+		let code = match (chars.next(), chars.next()) {
+			(Some(c), None) => KeyCode::Char(c),
+			_ => {
+				if let Some(n) = inner.strip_prefix('F').and_then(|x| x.parse().ok()) {
+					KeyCode::F(n)
+				} else {
+					NAMED_KEYS
+						.iter()
+						.find(|(name, _)| name.eq_ignore_ascii_case(inner))
+						.map(|(_, code)| *code)
+						.ok_or_else(|| format!("unknown key {inner:?} in {s:?}"))?
+				}
+			}
+		};
+		Ok(KeyChord { code, modifiers })
+	}
+}
+
+impl fmt::Display for KeyChord {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		let name = match self.code {
+			KeyCode::Char(' ') => "Space".to_string(),
+			KeyCode::Char(c) if self.modifiers.is_empty() => return write!(f, "{c}"),
+			KeyCode::Char(c) => c.to_string(),
+			KeyCode::F(n) => format!("F{n}"),
+			code => NAMED_KEYS
+				.iter()
+				.find(|(_, x)| *x == code)
+				.map(|(name, _)| name.to_string())
+				.unwrap_or_else(|| format!("{code:?}")),
+		};
+		write!(f, "<")?;
+		for (modifier, prefix) in [
+			(KeyModifiers::CONTROL, "C-"),
+			(KeyModifiers::ALT, "A-"),
+			(KeyModifiers::SHIFT, "S-"),
+		] {
+			if self.modifiers.contains(modifier) {
+				write!(f, "{prefix}")?;
+			}
+		}
+		write!(f, "{name}>")
+	}
+}
+
+/// Bindings from `config.json`, keyed by mode and then action name, overriding the defaults of the
+/// actions they mention. Names are only checked when building the `Keymap`, so a typo gets
+/// reported instead of making the whole config unreadable.
+pub type KeymapConfig = BTreeMap<String, BTreeMap<String, Vec<String>>>;
+
+/// The bindings of every mode, in the order they are listed in the footer
+#[derive(Debug, Default)]
+pub struct Keymap(BTreeMap<InputMode, Vec<(KeyChord, Action)>>);
+
+impl Keymap {
+	/// Combine the defaults with the user's bindings. Problems such as unknown keys or a key
+	/// bound to two actions are returned so they can be reported. The user's bindings win over
+	/// the defaults, otherwise the first binding wins.
+	pub fn new(config: &KeymapConfig) -> (Keymap, Vec<String>) {
+		let mut problems = vec![];
+		for name in config.keys() {
+			if !InputMode::ALL.iter().any(|x| x.name() == name) {
+				problems.push(format!("unknown mode {name}"));
+			}
+		}
+		let mut keymap = BTreeMap::new();
+		for mode in InputMode::ALL {
+			let defaults = default_bindings(mode);
+			let overrides = config.get(mode.name());
+			let (overridden, kept): (Vec<_>, Vec<_>) = defaults
+				.iter()
+				.map(
+					|(action, keys)| match overrides.and_then(|x| x.get(&action_name(action))) {
+						Some(keys) => (action, keys.iter().map(|x| x.as_str()).collect(), true),
+						None => (action, keys.to_vec(), false),
+					},
+				)
+				.partition(|(_, _, overridden)| *overridden);
+			let mut bindings: Vec<(KeyChord, Action)> = vec![];
+			for (action, keys, _) in overridden.into_iter().chain(kept) {
+				for key in keys {
+					let chord = match key.parse::<KeyChord>() {
+						Ok(x) => x,
+						Err(e) => {
+							problems.push(format!("{}: {e}", mode.name()));
+							continue;
+						}
+					};
+					if mode == InputMode::Normal
+						&& matches!(chord.code, KeyCode::Char(c) if chord.modifiers.is_empty() && VIM_KEYS.contains(c))
+					{
+						problems.push(format!(
+							"{}: {key} is reserved for counts, gg, G, zz and marks",
+							mode.name()
+						));
+						continue;
+					}
+					match bindings.iter().find(|(x, _)| x == &chord) {
+						Some((_, other)) => problems.push(format!(
+							"{}: {key} is bound to both {} and {}, using {}",
+							mode.name(),
+							action_name(other),
+							action_name(action),
+							action_name(other)
+						)),
+						None => bindings.push((chord, *action)),
+					}
+				}
+			}
+			for name in overrides.into_iter().flat_map(|x| x.keys()) {
+				if !defaults.iter().any(|(x, _)| &action_name(x) == name) {
+					problems.push(format!(
+						"{}: {name} is not an action of this mode",
+						mode.name()
+					));
+				}
+			}
+			// Keep the order of the defaults for the footer
+			bindings.sort_by_key(|(_, action)| defaults.iter().position(|(x, _)| x == action));
+			keymap.insert(mode, bindings);
+		}
+		(Keymap(keymap), problems)
+	}
+
+	pub fn lookup(&self, mode: InputMode, chord: &KeyChord) -> Option<Action> {
+		self.bindings(mode)
+			.iter()
+			.find(|(x, _)| x == chord)
+			.map(|(_, action)| *action)
+	}
+
+	pub fn bindings(&self, mode: InputMode) -> &[(KeyChord, Action)] {
+		self.0.get(&mode).map(|x| x.as_slice()).unwrap_or_default()
+	}
+
+	/// The first key bound to each action of `mode` that belongs in the footer
+	pub fn footer(&self, mode: InputMode) -> Vec<(KeyChord, Action)> {
+		let mut footer: Vec<(KeyChord, Action)> = vec![];
+		for (chord, action) in self.bindings(mode) {
+			if action.in_footer() && !footer.iter().any(|(_, x)| x == action) {
+				footer.push((*chord, *action));
+			}
+		}
+		footer
+	}
+
+	/// Every action of `mode` in the order of the defaults, with all keys bound to it. Actions
+	/// whose keys were all removed in the config are included with no keys.
+	pub fn help(&self, mode: InputMode) -> Vec<(Action, Vec<KeyChord>)> {
+		default_bindings(mode)
+			.iter()
+			.map(|(action, _)| {
+				let chords = self
+					.bindings(mode)
+					.iter()
+					.filter(|(_, x)| x == action)
+					.map(|(chord, _)| *chord)
+					.collect();
+				(*action, chords)
+			})
+			.collect()
+	}
+}
+
+/// The name of an action as written in `config.json`
+pub fn action_name(action: &Action) -> String {
+	serde_json::to_value(action)
+		.ok()
+		.and_then(|x| x.as_str().map(|x| x.to_string()))
+		.unwrap_or_else(|| format!("{action:?}"))
+}
+
+#[test]
+pub fn test_keymap() {
+	for key in [
+		"j",
+		"<C-d>",
+		"<Esc>",
+		"<A-S-Left>",
+		"<Space>",
+		"<F5>",
+		"<C-->",
+	] {
+		assert_eq!(key.parse::<KeyChord>().unwrap().to_string(), key);
+	}
+	assert!("<Nope>".parse::<KeyChord>().is_err());
+	assert!("<aé>".parse::<KeyChord>().is_err());
+	assert!("<éa>".parse::<KeyChord>().is_err());
+	assert_eq!("<C-é>".parse::<KeyChord>().unwrap().to_string(), "<C-é>");
+
+	let config: KeymapConfig = serde_json::from_str(
+		r#"{ "normal": { "diff-mark": ["d"], "list-down": ["<C-n>"], "search-next": ["n"] } }"#,
+	)
+	.unwrap();
+	let (keymap, problems) = Keymap::new(&config);
+	let chord = |x: &str| x.parse::<KeyChord>().unwrap();
+	assert_eq!(
+		keymap.lookup(InputMode::Normal, &chord("<C-n>")),
+		Some(Action::ListDown)
+	);
+	assert_eq!(keymap.lookup(InputMode::Normal, &chord("j")), None);
+	assert_eq!(
+		keymap.lookup(InputMode::Normal, &chord("d")),
+		Some(Action::DiffMark)
+	);
+	assert_eq!(
+		problems,
+		vec![
+			"normal: d is bound to both diff-mark and delete-bookmark, using diff-mark",
+			"normal: search-next is not an action of this mode"
+		]
+	);
+	let help = keymap.help(InputMode::Normal);
+	assert!(help.contains(&(Action::ListDown, vec![chord("<C-n>")])));
+	assert!(help.contains(&(Action::DeleteBookmark, vec![])));
+}
@@ -0,0 +1,145 @@
+use ratatui::layout::{Constraint, Direction, Layout, Rect};
+use serde::{Deserialize, Serialize};
+
+/// The `layout` section of `config.json`
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(default)]
+pub struct LayoutConfig {
+	/// Relative widths of the parent, current and preview columns
+	pub columns: [u16; 3],
+	/// Terminal width below which the current list and the preview are stacked instead
+	pub stack_below: u16,
+}
+
+impl Default for LayoutConfig {
+	fn default() -> Self {
+		LayoutConfig {
+			columns: [2, 5, 3],
+			stack_below: 80,
+		}
+	}
+}
+
+impl LayoutConfig {
+	pub fn is_default(&self) -> bool {
+		*self == LayoutConfig::default()
+	}
+}
+
+/// Which columns are shown, toggled with `Z`
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Zoom {
+	#[default]
+	Off,
+	/// The parent column is collapsed
+	Focus,
+	/// The preview takes up the full width
+	Preview,
+}
+
+impl Zoom {
+	pub fn next(self) -> Zoom {
+		match self {
+			Zoom::Off => Zoom::Focus,
+			Zoom::Focus => Zoom::Preview,
+			Zoom::Preview => Zoom::Off,
+		}
+	}
+}
+
+/// Where each column goes. Hidden columns are empty.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Columns {
+	pub parent: Rect,
+	pub current: Rect,
+	pub preview: Rect,
+	/// Whether the current list and the preview are above each other
+	pub stacked: bool,
+	/// Column left of the preview for its left border, when nothing is drawn there
+	pub preview_edge: Option<Rect>,
+}
+
+pub fn columns(area: Rect, config: &LayoutConfig, zoom: Zoom) -> Columns {
+	let empty = Rect::new(area.x, area.y, 0, area.height);
+	let with_edge = |preview: Rect| {
+		let edge = Rect::new(preview.x, preview.y, 1.min(preview.width), preview.height);
+		let preview = Rect {
+			x: preview.x + edge.width,
+			width: preview.width - edge.width,
+			..preview
+		};
+		(preview, Some(edge))
+	};
+	if zoom == Zoom::Preview {
+		let (preview, preview_edge) = with_edge(area);
+		return Columns {
+			parent: empty,
+			current: empty,
+			preview,
+			stacked: false,
+			preview_edge,
+		};
+	}
+	if area.width < config.stack_below {
+		let rows = Layout::default()
+			.direction(Direction::Vertical)
+			.constraints([Constraint::Fill(1), Constraint::Fill(1)])
+			.split(area);
+		let (preview, preview_edge) = with_edge(rows[1]);
+		return Columns {
+			parent: empty,
+			current: rows[0],
+			preview,
+			stacked: true,
+			preview_edge,
+		};
+	}
+	let [parent, current, preview] = config.columns;
+	let show_parent = parent > 0 && zoom != Zoom::Focus;
+	let weights = [parent, current.max(1), preview];
+	let constraints = weights[if show_parent { 0 } else { 1 }..]
+		.iter()
+		.map(|x| Constraint::Fill(*x));
+	let split = Layout::default()
+		.direction(Direction::Horizontal)
+		.constraints(constraints)
+		.split(area);
+	let (parent, rest) = match show_parent {
+		true => (split[0], &split[1..]),
+		false => (empty, &split[..]),
+	};
+	Columns {
+		parent,
+		current: rest[0],
+		preview: rest[1],
+		stacked: false,
+		preview_edge: None,
+	}
+}
+
+#[test]
+pub fn test_columns() {
+	let config = LayoutConfig::default();
+	let wide = Rect::new(0, 1, 100, 40);
+
+	let normal = columns(wide, &config, Zoom::Off);
+	assert_eq!(
+		(
+			normal.parent.width,
+			normal.current.width,
+			normal.preview.width
+		),
+		(20, 50, 30)
+	);
+	let focus = columns(wide, &config, Zoom::Focus);
+	assert_eq!(focus.parent.width, 0);
+	assert_eq!(focus.current.x, 0);
+	let preview = columns(wide, &config, Zoom::Preview);
+	assert_eq!(preview.current.width, 0);
+	assert_eq!(preview.preview, Rect::new(1, 1, 99, 40));
+
+	let narrow = columns(Rect::new(0, 1, 60, 40), &config, Zoom::Off);
+	assert!(narrow.stacked);
+	assert_eq!(narrow.current, Rect::new(0, 1, 60, 20));
+	assert_eq!(narrow.preview, Rect::new(1, 21, 59, 20));
+}
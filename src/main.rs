@@ -1,14 +1,15 @@
 use std::{
 	env,
-	fs::{create_dir_all, File},
-	io::{stdout, Read},
+	fs::create_dir_all,
+	io::stdout,
 	path::{Path, PathBuf},
-	sync::Arc,
 };
 
+use bookmarks::BookmarkConfig;
+use cache::CacheConfig;
 use clap::Parser;
-use color_eyre::eyre::OptionExt;
 use crossterm::{
+	event::{DisableMouseCapture, EnableMouseCapture},
 	terminal::{
 		disable_raw_mode, enable_raw_mode, size, EnterAlternateScreen, LeaveAlternateScreen,
 		SetSize,
@@ -16,36 +17,84 @@ use crossterm::{
 	ExecutableCommand,
 };
 use key_handler::register_key_handler;
+use keymap::{Keymap, KeymapConfig};
+use layout::LayoutConfig;
 use logging::{initialize_logging, project_directory};
-use model::{Bookmark, BrowserPath, BrowserStack, BrowserStackItem, Message, Model, RunningState};
+use model::{
+	BrowserPath, BrowserStack, BrowserStackItem, Message, Model, PathDataMap, RunningState,
+	ROOT_ENTRY,
+};
 use ratatui::{backend::CrosstermBackend, widgets::ListState, Terminal};
 use serde::{Deserialize, Serialize};
+use sort::{SortMode, SortState};
+use theme::{no_color, set_theme, Theme, ThemeConfig};
 use update::UpdateContext;
 use view::view;
-use workers::WorkerHost;
 
-use crate::view::ViewData;
+use crate::{diff::DiffState, view::ViewData};
 
+pub mod attr_path;
+pub mod batch;
+pub mod bookmarks;
+pub mod cache;
+pub mod clipboard;
+pub mod diff;
+pub mod discover;
+pub mod disk_cache;
+pub mod eval;
+pub mod file_preview;
 pub mod key_handler;
+pub mod keymap;
+pub mod layout;
 pub mod logging;
 pub mod model;
+pub mod services;
+pub mod sort;
+pub mod stable_hash;
+pub mod string_view;
+pub mod system_compare;
+pub mod theme;
+pub mod tree;
 pub mod tui;
 pub mod update;
 pub mod view;
+pub mod watcher;
 pub mod workers;
 
 #[derive(Serialize, Deserialize, Default, Debug, Clone)]
 pub struct Config {
-	bookmarks: Vec<Bookmark>,
+	#[serde(flatten)]
+	bookmarks: BookmarkConfig,
+	#[serde(default, skip_serializing_if = "KeymapConfig::is_empty")]
+	keymap: KeymapConfig,
+	#[serde(default, skip_serializing_if = "ThemeConfig::is_default")]
+	theme: ThemeConfig,
+	#[serde(default, skip_serializing_if = "LayoutConfig::is_default")]
+	layout: LayoutConfig,
+	#[serde(default, skip_serializing_if = "CacheConfig::is_default")]
+	cache: CacheConfig,
+	/// Sort mode of lists until it is changed with `O`
+	#[serde(default, skip_serializing_if = "SortMode::is_default")]
+	sort: SortMode,
 }
 
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None)]
-struct Args {
+pub struct Args {
 	#[arg(short, long)]
 	path: Option<String>,
 	#[arg(short, long)]
 	expr: Option<String>,
+	/// Diff against the config at this path
+	#[arg(long, conflicts_with = "diff_expr")]
+	diff_path: Option<String>,
+	/// Diff against this expression
+	#[arg(long)]
+	diff_expr: Option<String>,
+	/// Re-evaluate whenever files in the directory of the config change. Files it imports from
+	/// elsewhere are not watched, press R after changing those.
+	#[arg(short, long)]
+	watch: bool,
 }
 
 pub fn find_in_nix_path() -> color_eyre::Result<String> {
@@ -61,15 +110,7 @@ fn load_config(args: &Args) -> color_eyre::Result<String> {
 	if let Some(expr) = &args.expr {
 		Ok(expr.to_string())
 	} else if let Some(path) = &args.path {
-		let path = Path::new(path).canonicalize()?;
-		let is_file = path.is_file();
-		let is_flake = is_file && path.ends_with("flake.nix") || path.join("flake.nix").exists();
-
-		Ok(if is_flake {
-			format!(r#"builtins.getFlake "{}""#, path.display())
-		} else {
-			format!("(import <nixpkgs/nixos>) {{ system = builtins.currentSystem; configuration = import {}; }}", path.display())
-		})
+		expr_from_path(path)
 	} else {
 		let nixos_path = Path::new("/etc/nixos").canonicalize()?;
 		let etc_nixos_flake = nixos_path.join("flake.nix");
@@ -91,6 +132,71 @@ fn load_config(args: &Args) -> color_eyre::Result<String> {
 	}
 }
 
+fn expr_from_path(path: &str) -> color_eyre::Result<String> {
+	let path = Path::new(path).canonicalize()?;
+	let is_file = path.is_file();
+	let is_flake = is_file && path.ends_with("flake.nix") || path.join("flake.nix").exists();
+
+	Ok(if is_flake {
+		format!(r#"builtins.getFlake "{}""#, path.display())
+	} else {
+		format!("(import <nixpkgs/nixos>) {{ system = builtins.currentSystem; configuration = import {}; }}", path.display())
+	})
+}
+
+/// The root expression of the right hand side when diffing against a second config
+fn load_diff_config(args: &Args) -> color_eyre::Result<Option<String>> {
+	if let Some(expr) = &args.diff_expr {
+		Ok(Some(expr.to_string()))
+	} else if let Some(path) = &args.diff_path {
+		expr_from_path(path).map(Some)
+	} else {
+		Ok(None)
+	}
+}
+
+/// Directory holding `path`, or `path` itself if it is one
+fn directory_of(path: &str) -> Option<PathBuf> {
+	let path = Path::new(path).canonicalize().ok()?;
+	Some(match path.parent() {
+		Some(parent) if path.is_file() => parent.to_path_buf(),
+		_ => path,
+	})
+}
+
+/// Directory holding the files behind the root expression. Plain expressions have none.
+fn root_dir(args: &Args) -> Option<PathBuf> {
+	let root = if args.expr.is_some() {
+		None
+	} else if let Some(path) = &args.path {
+		Some(path.clone())
+	} else if Path::new("/etc/nixos/flake.nix").exists() {
+		Some("/etc/nixos".to_string())
+	} else {
+		find_in_nix_path().ok()
+	};
+	directory_of(&root?)
+}
+
+/// Directories holding the files behind the root expressions, watched in `--watch` mode. Plain
+/// expressions have nothing to watch. Nix doesn't tell which files an evaluation read, so
+/// imports from outside these directories, like paths in other checkouts or in `$HOME`, are
+/// not noticed.
+fn watch_paths(args: &Args) -> Vec<PathBuf> {
+	root_dir(args)
+		.into_iter()
+		.chain(args.diff_path.as_deref().and_then(directory_of))
+		.collect()
+}
+
+/// Key of the disk cache for the current state of the root expression and its files
+fn disk_cache_key(args: &Args) -> color_eyre::Result<u64> {
+	Ok(disk_cache::cache_key(
+		&load_config(args)?,
+		root_dir(args).as_deref(),
+	))
+}
+
 pub fn read_config(p: PathBuf) -> anyhow::Result<Config> {
 	let config = std::fs::read_to_string(p)?;
 	let cfg: Config = serde_json::from_str(&config)?;
@@ -102,6 +208,7 @@ fn main() -> color_eyre::Result<()> {
 	let (cols, rows) = size()?;
 	enable_raw_mode()?;
 	stdout().execute(EnterAlternateScreen)?;
+	stdout().execute(EnableMouseCapture)?;
 	let mut terminal = Terminal::new(CrosstermBackend::new(stdout()))?;
 	initialize_logging()?;
 	tui::install_panic_hook();
@@ -111,26 +218,8 @@ fn main() -> color_eyre::Result<()> {
 	let config = if let Ok(c) = read_config(config_path.clone()) {
 		c
 	} else {
-		// Same behavior as nixos-rebuild
-		let hostname = nix::unistd::gethostname()?;
-		let hostname = hostname.to_string_lossy();
-		let hostname_path = format!(".nixosConfigurations.{}", hostname);
-
-		let user = env::var("USER")?;
-		let user_path = format!("{hostname_path}.config.home-manager.users.{user}");
-
-		let config = Config {
-			bookmarks: vec![
-				Bookmark {
-					display: hostname.to_string(),
-					path: BrowserPath::from(hostname_path),
-				},
-				Bookmark {
-					display: user.to_string(),
-					path: BrowserPath::from(user_path.to_string()),
-				},
-			],
-		};
+		// Hosts and users are discovered by evaluating the root, so there is nothing to guess
+		let config = Config::default();
 		create_dir_all(config_path.parent().unwrap())?;
 		let x = serde_json::to_string_pretty(&config)?;
 		std::fs::write(config_path.clone(), x)?;
@@ -138,40 +227,81 @@ fn main() -> color_eyre::Result<()> {
 		config
 	};
 
-	let expr = load_config(&args)?;
-	tracing::debug!("{}", expr);
+	let (tx, rx) = kanal::unbounded::<Message>();
+	register_key_handler(&tx);
 
-	let worker_host = WorkerHost::new(expr);
+	// Kept alive for as long as the app runs, dropping it stops watching
+	let _watcher = if args.watch {
+		Some(watcher::watch(&watch_paths(&args), tx.clone())?)
+	} else {
+		None
+	};
+
+	let (keymap, keymap_problems) = Keymap::new(&config.keymap);
+	for problem in &keymap_problems {
+		tracing::warn!("keymap: {problem}");
+	}
+	let (theme, theme_problem) = Theme::new(&config.theme, no_color());
+	if let Some(problem) = &theme_problem {
+		tracing::warn!("theme: {problem}");
+	}
+	set_theme(theme);
+	let mut problems = vec![];
+	if !keymap_problems.is_empty() {
+		problems.push(format!("Keymap: {}", keymap_problems.join("; ")));
+	}
+	problems.extend(theme_problem.map(|x| format!("Theme: {x}")));
+
+	let root_id = bookmarks::root_id(&load_config(&args)?, root_dir(&args).as_deref());
 	let mut model = Model {
 		running_state: RunningState::Running,
+		root_id,
+		path_data: PathDataMap::with_limit(config.cache.max_bytes()),
+		sort: SortState {
+			global: config.sort,
+			..Default::default()
+		},
 		visit_stack: BrowserStack(vec![BrowserStackItem::Root]),
 		root_view_state: ListState::default().with_selected(Some(0)),
 		bookmark_view_state: ListState::default().with_selected(Some(0)),
 		config,
+		keymap,
+		status_message: (!problems.is_empty()).then(|| problems.join(" | ")),
 		..Default::default()
 	};
 
-	let mut update_context = UpdateContext {
-		req_tx: worker_host.tx.clone(),
-		config_path,
-	};
-
-	let (tx, rx) = kanal::unbounded::<Message>();
-	register_key_handler(&tx);
-
-	{
-		let worker_rx = worker_host.rx.clone();
-		std::thread::spawn(move || loop {
-			match worker_rx.recv() {
-				Ok((p, v)) => {
-					let _ = tx.send(Message::Data(p, v));
+	if model.config.cache.disk {
+		match disk_cache::load(disk_cache_key(&args)?) {
+			Ok(entries) => {
+				for (path, data) in entries {
+					model.path_data.insert_stale(path, data);
 				}
-				Err(_) => break,
 			}
-		});
+			Err(e) => tracing::info!("no disk cache: {e}"),
+		}
+	}
+
+	let mut update_context = UpdateContext::new(args, config_path, tx)?;
+
+	for path in discover::discovery_paths(&model.path_data) {
+		update_context.maybe_reeval_path(&path, &model);
+	}
+
+	if update_context.diff_req_tx.is_some() {
+		// Start out diffing the two roots against each other
+		let root = BrowserPath::from("".to_string());
+		model.diff = Some(DiffState::new(root.clone(), root.clone(), true));
+		model.root_view_state.select(Some(ROOT_ENTRY));
+		model.visit_stack.push_path(root.clone());
+		update_context.queue_reeval(&root);
+		update_context.maybe_reeval_diff(&mut model);
 	}
 
 	while model.running_state != RunningState::Stopped {
+		if std::mem::take(&mut update_context.clear_terminal) {
+			terminal.clear()?;
+		}
+
 		// Render the current view
 		let mut view_data: ViewData = ViewData::default();
 		terminal.draw(|f| {
@@ -180,17 +310,35 @@ fn main() -> color_eyre::Result<()> {
 
 		let mut current_msg = Some(rx.recv()?);
 
-		// Process updates as long as they return a non-None message
-		while let Some(msg) = current_msg {
-			tracing::info!("{:?}", msg);
-			if let Ok(msg) = update_context.update(&view_data, &mut model, msg) {
-				current_msg = msg;
-			} else {
-				current_msg = None;
+		// Handle everything that is already waiting before drawing again
+		while current_msg.is_some() && model.running_state != RunningState::Stopped {
+			// Process updates as long as they return a non-None message
+			while let Some(msg) = current_msg {
+				tracing::info!("{:?}", msg);
+				current_msg = match update_context.update(&view_data, &mut model, msg) {
+					Ok(msg) => msg,
+					Err(e) => {
+						tracing::error!("Handling a message failed: {e:?}");
+						model.status_message = Some(format!("Error: {e}"));
+						None
+					}
+				};
 			}
+			current_msg = rx.try_recv()?;
+		}
+		update_context.sort_unsorted(&mut model);
+	}
+
+	if model.config.cache.disk {
+		let saved = disk_cache_key(&update_context.args)
+			.map_err(anyhow::Error::msg)
+			.and_then(|key| disk_cache::save(key, &model.path_data));
+		if let Err(e) = saved {
+			tracing::warn!("saving the disk cache failed: {e}");
 		}
 	}
 
+	stdout().execute(DisableMouseCapture)?;
 	stdout().execute(LeaveAlternateScreen)?;
 	disable_raw_mode()?;
 	stdout().execute(SetSize(cols, rows))?;
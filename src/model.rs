@@ -1,14 +1,40 @@
 use std::{
-	collections::HashMap,
+	cell::Cell,
+	collections::{HashMap, HashSet},
 	fmt,
 	ops::{Deref, DerefMut},
 };
 
 use crossterm::event::{KeyCode, KeyEvent};
-use ratatui::{text::Text, widgets::ListState};
+use ratatui::widgets::ListState;
 use serde::{Deserialize, Deserializer, Serialize};
 
-use crate::{workers::NixValue, Config};
+use crate::{
+	attr_path,
+	batch::BatchState,
+	bookmarks::{BookmarkField, BookmarkRef, BookmarkRow},
+	cache::{entry_size, CacheStats},
+	diff::DiffState,
+	discover::{discovered_paths, root_entries, RootEntry},
+	file_preview::{FilePreview, FilePreviews, STORE_DIR},
+	key_handler::PendingKeys,
+	keymap::{Action, InputMode, Keymap},
+	layout::Zoom,
+	services::Services,
+	sort::SortState,
+	string_view::{find_line, line_count},
+	system_compare::{SystemCompare, COMPONENTS},
+	tree::TreeState,
+	workers::NixValue,
+	Config,
+};
+
+/// Fixed entries of the root menu, followed by the discovered hosts and users
+pub const ROOT_ENTRIES: [&str; 4] = ["Bookmarks", "Recents", "Services", "Root"];
+/// Position of the services dashboard in the root menu
+pub const SERVICES_ENTRY: usize = 2;
+/// Position of the root expression in the root menu
+pub const ROOT_ENTRY: usize = 3;
 
 #[derive(Default, Debug)]
 pub struct Model {
@@ -18,12 +44,21 @@ pub struct Model {
 	pub recents: Vec<BrowserPath>,
 
 	pub config: Config,
+	/// Identifier of the root expression, which its bookmarks are stored under
+	pub root_id: String,
+	pub keymap: Keymap,
+	pub pending_keys: PendingKeys,
+	/// Paths stored with `m{a-z}`, for jumping back with `'{a-z}`
+	pub marks: HashMap<char, BrowserPath>,
 
 	pub visit_stack: BrowserStack,
 
 	pub search_input: InputState,
 	pub path_navigator_input: InputState,
 	pub new_bookmark_input: InputState,
+	/// Bookmark and field the bookmark input changes, instead of creating a bookmark
+	pub bookmark_edit: Option<(BookmarkRef, BookmarkField)>,
+	pub eval_input: InputState,
 
 	/// TODO: things that the architecture doesnt handle all that well
 	pub prev_tab_completion: Option<String>,
@@ -31,13 +66,102 @@ pub struct Model {
 	pub root_view_state: ListState,
 	pub bookmark_view_state: ListState,
 	pub recents_view_state: ListState,
+	pub string_view_state: StringViewState,
+	pub file_previews: FilePreviews,
+
+	pub diff: Option<DiffState>,
+	/// Path marked as the left hand side of the next diff
+	pub diff_mark: Option<BrowserPath>,
+
+	pub system_compare: Option<SystemCompare>,
+	pub services: Option<Services>,
+
+	/// Selected item of each list on the visit stack before a reload, applied once the list has
+	/// been evaluated again
+	pub restored_selections: HashMap<BrowserPath, String>,
+
+	/// Feedback about the last action, shown until the next key press
+	pub status_message: Option<String>,
+
+	/// Set while the help overlay is open
+	pub help: Option<HelpState>,
+
+	pub zoom: Zoom,
+	/// Set while the tree view is shown instead of the columns
+	pub tree: Option<TreeState>,
+	/// Whether the panel with the counters of the `PathDataMap` is shown
+	pub show_cache_stats: bool,
+	pub sort: SortState,
+	/// Set while the children of a list are evaluated with `E`, until the table is closed
+	pub batch: Option<BatchState>,
+}
+
+#[derive(Debug)]
+pub struct HelpState {
+	/// Mode the help was opened from, which is listed first
+	pub mode: InputMode,
+	pub scroll: usize,
+	/// Lines scrolled by half a page, set when the overlay is drawn
+	pub half_page: usize,
 }
 
 impl Model {
+	/// Bookmarks of the root and the global ones, as listed
+	pub fn bookmark_rows(&self) -> Vec<BookmarkRow> {
+		self.config.bookmarks.rows(&self.root_id)
+	}
+
+	pub fn selected_bookmark_row(&self) -> Option<BookmarkRow> {
+		let i = self.bookmark_view_state.selected()?;
+		self.bookmark_rows().into_iter().nth(i)
+	}
+
+	pub fn selected_bookmark_ref(&self) -> Option<BookmarkRef> {
+		match self.selected_bookmark_row()? {
+			BookmarkRow::Bookmark(x) => Some(x),
+			BookmarkRow::Group { .. } => None,
+		}
+	}
+
+	/// The selected bookmark, if the bookmark list is the current one
+	pub fn editable_bookmark(&self) -> Option<BookmarkRef> {
+		match self.visit_stack.last() {
+			Some(BrowserStackItem::Bookmarks) => self.selected_bookmark_ref(),
+			_ => None,
+		}
+	}
+
+	/// Move the cursor of the bookmark list to `x`
+	pub fn select_bookmark(&mut self, x: BookmarkRef) {
+		let i = self
+			.bookmark_rows()
+			.iter()
+			.position(|row| *row == BookmarkRow::Bookmark(x));
+		if i.is_some() {
+			self.bookmark_view_state.select(i);
+		}
+	}
+
 	pub fn selected_bookmark(&self) -> Option<&Bookmark> {
-		self.bookmark_view_state
-			.selected()
-			.and_then(|i| self.config.bookmarks.get(i))
+		let x = self.selected_bookmark_ref()?;
+		self.config.bookmarks.get(x, &self.root_id)
+	}
+
+	/// Hosts and users listed in the root menu below `ROOT_ENTRIES`
+	pub fn root_entries(&self) -> Vec<RootEntry> {
+		root_entries(&self.path_data)
+	}
+
+	pub fn root_menu_len(&self) -> usize {
+		ROOT_ENTRIES.len() + self.root_entries().len()
+	}
+
+	pub fn selected_root_entry(&self) -> Option<RootEntry> {
+		let i = self
+			.root_view_state
+			.selected()?
+			.checked_sub(ROOT_ENTRIES.len())?;
+		self.root_entries().into_iter().nth(i)
 	}
 
 	pub fn selected_recent(&self) -> Option<&BrowserPath> {
@@ -46,6 +170,210 @@ impl Model {
 			.and_then(|i| self.recents.get(i))
 	}
 
+	/// The current path and the text shown for it in the string viewer, which is either the
+	/// string itself or the contents of the file it points to
+	pub fn current_string(&self) -> Option<(&BrowserPath, &str)> {
+		let path = self.visit_stack.current()?;
+		let data = self.path_data.get(path)?;
+		if let Some(preview) = data.file_target().and_then(|x| self.file_previews.get(x)) {
+			return preview.text().map(|x| (path, x));
+		}
+		match data {
+			PathData::String(s) => Some((path, s.as_str())),
+			_ => None,
+		}
+	}
+
+	/// The current path and its number of lines, if it is shown in the string viewer. Besides
+	/// strings, this includes the text files and directories that paths point to.
+	fn current_view_lines(&self) -> Option<(&BrowserPath, usize)> {
+		let path = self.visit_stack.current()?;
+		let data = self.path_data.get(path)?;
+		if let Some(preview) = data.file_target().and_then(|x| self.file_previews.get(x)) {
+			return preview.line_count().map(|x| (path, x));
+		}
+		match data {
+			PathData::String(s) => Some((path, line_count(s))),
+			_ => None,
+		}
+	}
+
+	/// Scroll the string viewer by `delta` lines, returns false if no string is being viewed
+	pub fn scroll_string_view(&mut self, delta: isize) -> bool {
+		let (path, lines) = match self.current_view_lines() {
+			Some((path, lines)) => (path.clone(), lines),
+			None => return false,
+		};
+		let scroll = self.string_view_state.scroll_for(&path);
+		*scroll = scroll
+			.saturating_add_signed(delta)
+			.min(lines.saturating_sub(1));
+		true
+	}
+
+	/// Move the string viewer to the nearest line matching `needle`, starting `offset` lines from
+	/// the current scroll position. Returns false if no string is being viewed
+	pub fn search_string_view(&mut self, needle: &str, offset: isize) -> bool {
+		let path = match self.current_string() {
+			Some((path, _)) => path.clone(),
+			None => return false,
+		};
+		let from = self
+			.string_view_state
+			.scroll_for(&path)
+			.saturating_add_signed(offset);
+		let found = self
+			.current_string()
+			.and_then(|(_, content)| find_line(content, needle, from, offset >= 0));
+		if let Some(line) = found {
+			self.string_view_state.scroll = line;
+		}
+		true
+	}
+
+	/// The `config` of the NixOS configuration under the cursor, falling back to the
+	/// configuration of this host like nixos-rebuild does, or to the only one there is. `None`
+	/// until the configurations of the root have been evaluated, or if none of them fits.
+	pub fn system_config_root(&self) -> Option<BrowserPath> {
+		let cursor = self.visit_stack.current().map(|x| {
+			self.path_data
+				.current_list(x)
+				.and_then(|list| list.selected(x))
+				.unwrap_or_else(|| x.clone())
+		});
+		if let Some(path) = cursor {
+			if let Some(i) = path.0.iter().position(|x| x == "config") {
+				return Some(BrowserPath(path.0[..=i].to_vec()));
+			}
+			if path.0.len() >= 3 && path.0[1] == "nixosConfigurations" {
+				return Some(BrowserPath(path.0[..3].to_vec()).child("config".to_string()));
+			}
+		}
+		let root = BrowserPath::from("".to_string());
+		let root_list = self.path_data.current_list(&root)?;
+		if root_list.list.iter().any(|x| x == "config") {
+			return Some(root.child("config".to_string()));
+		}
+		let set = root.child("nixosConfigurations".to_string());
+		let hosts = &self.path_data.current_list(&set)?.list;
+		let hostname = nix::unistd::gethostname()
+			.map(|x| x.to_string_lossy().to_string())
+			.unwrap_or_default();
+		let host = match hosts.as_slice() {
+			[host] => host,
+			_ => hosts.iter().find(|x| **x == hostname)?,
+		};
+		Some(set.child(host.clone()).child("config".to_string()))
+	}
+
+	/// Select `name` again in the freshly evaluated list at `path`. If it no longer exists, the
+	/// levels below it are dropped from the visit stack.
+	pub fn restore_selection(&mut self, path: &BrowserPath, name: &str) {
+		let list = match self.path_data.current_list_mut(path) {
+			Some(x) => x,
+			None => return,
+		};
+		if let Some(i) = list.list.iter().position(|x| x == name) {
+			list.state.select(Some(i));
+			return;
+		}
+		let depth = self
+			.visit_stack
+			.iter()
+			.position(|x| matches!(x, BrowserStackItem::BrowserPath(x) if x == path));
+		if let Some(depth) = depth {
+			if self.visit_stack.get(depth + 1).is_some_and(
+				|x| matches!(x, BrowserStackItem::BrowserPath(x) if x.parent().as_ref() == Some(path)),
+			) {
+				self.visit_stack.truncate(depth + 1);
+			}
+		}
+	}
+
+	/// State and length of the list the cursor is in
+	pub fn current_list_state(&mut self) -> Option<(&mut ListState, usize)> {
+		self.list_state(self.visit_stack.len().checked_sub(1)?)
+	}
+
+	/// State and length of the list shown for the visit stack item at `depth`
+	pub fn list_state(&mut self, depth: usize) -> Option<(&mut ListState, usize)> {
+		match self.visit_stack.get(depth)? {
+			BrowserStackItem::Root => {
+				let len = self.root_menu_len();
+				Some((&mut self.root_view_state, len))
+			}
+			BrowserStackItem::BrowserPath(p) => {
+				let list = self.path_data.current_list_mut(p)?;
+				Some((&mut list.state, list.list.len()))
+			}
+			BrowserStackItem::Bookmarks => {
+				let len = self.bookmark_rows().len();
+				Some((&mut self.bookmark_view_state, len))
+			}
+			BrowserStackItem::Recents => Some((&mut self.recents_view_state, self.recents.len())),
+			BrowserStackItem::SystemCompare => self
+				.system_compare
+				.as_mut()
+				.map(|x| (&mut x.state, COMPONENTS.len())),
+			BrowserStackItem::Services => {
+				let services = self.services.as_mut()?;
+				let len = services.entries(&self.path_data).len();
+				Some((&mut services.state, len))
+			}
+		}
+	}
+
+	/// The value under the cursor: the selected node of the tree view, the selected item of the
+	/// current list, or the current value. Falls back to the root.
+	pub fn cursor_path(&self) -> BrowserPath {
+		if let Some(tree) = &self.tree {
+			return tree.selected.clone();
+		}
+		let path = match self.visit_stack.last() {
+			Some(BrowserStackItem::BrowserPath(p)) => Some(
+				self.path_data
+					.current_list(p)
+					.and_then(|x| x.selected(p))
+					.unwrap_or_else(|| p.clone()),
+			),
+			Some(BrowserStackItem::Root) => self.selected_root_entry().map(|x| x.path),
+			Some(BrowserStackItem::Bookmarks) => self.selected_bookmark().map(|x| x.path.clone()),
+			Some(BrowserStackItem::Recents) => self.selected_recent().cloned(),
+			Some(BrowserStackItem::SystemCompare) => {
+				self.system_compare.as_ref().and_then(|x| x.selected_path())
+			}
+			Some(BrowserStackItem::Services) => self
+				.services
+				.as_ref()
+				.and_then(|x| x.selected_path(&self.path_data)),
+			_ => None,
+		};
+		path.unwrap_or_else(|| BrowserPath::from(String::new()))
+	}
+
+	/// Paths that are never evicted from the `PathDataMap`: everything on the visit stack, the
+	/// value under the cursor, the expanded nodes of the tree view, the lists the hosts and users
+	/// of the root menu are found in and what the services dashboard is made of
+	pub fn pinned_paths(&self) -> HashSet<BrowserPath> {
+		let stack = self.visit_stack.iter().filter_map(|x| match x {
+			BrowserStackItem::BrowserPath(p) => Some(p.clone()),
+			_ => None,
+		});
+		let tree = self.tree.iter().flat_map(|x| x.expanded.iter().cloned());
+		let services = self.services.iter().flat_map(|services| {
+			services.group_paths().into_iter().flat_map(move |group| {
+				let enable_paths = services.enable_paths(&group, &self.path_data);
+				std::iter::once(group).chain(enable_paths)
+			})
+		});
+		stack
+			.chain(tree)
+			.chain(discovered_paths(&self.path_data))
+			.chain(services)
+			.chain(std::iter::once(self.cursor_path()))
+			.collect()
+	}
+
 	/// Update the selection of the parent to match the current path
 	pub fn update_parent_selection(&mut self, current_path: BrowserPath) {
 		let mut new_stack = vec![];
@@ -61,29 +389,210 @@ impl Model {
 			path = parent;
 		}
 		new_stack.push(BrowserStackItem::Root);
-		self.root_view_state.select(Some(2));
+		self.root_view_state.select(Some(ROOT_ENTRY));
 		new_stack.reverse();
 		*self.visit_stack = new_stack;
 	}
 }
 
+/// Scroll position of the string viewer, which is reset whenever a different path is viewed
+#[derive(Default, Debug)]
+pub struct StringViewState {
+	pub path: BrowserPath,
+	pub scroll: usize,
+}
+
+impl StringViewState {
+	pub fn scroll_for(&mut self, path: &BrowserPath) -> &mut usize {
+		if &self.path != path {
+			self.path = path.clone();
+			self.scroll = 0;
+		}
+		&mut self.scroll
+	}
+}
+
+/// Evaluated values by path. Lookups through [`PathDataMap::lookup`] are counted and keep
+/// entries from being evicted, and once a limit is set, [`PathDataMap::evict`] drops the least
+/// recently used entries that are not pinned. Values loaded from the disk cache are stale until
+/// the worker sends them again.
 #[derive(Default, Debug)]
-pub struct PathDataMap(HashMap<BrowserPath, PathData>);
+pub struct PathDataMap {
+	map: HashMap<BrowserPath, PathData>,
+	/// Value of `clock` when each entry was last stored or looked up
+	last_used: HashMap<BrowserPath, Cell<u64>>,
+	clock: Cell<u64>,
+	bytes: usize,
+	max_bytes: Option<usize>,
+	hits: Cell<u64>,
+	misses: Cell<u64>,
+	evictions: u64,
+	/// Values from the disk cache, with whether the worker has been asked to confirm them
+	stale: HashMap<BrowserPath, Cell<bool>>,
+}
 
 impl Deref for PathDataMap {
 	type Target = HashMap<BrowserPath, PathData>;
 
 	fn deref(&self) -> &Self::Target {
-		&self.0
-	}
-}
-impl DerefMut for PathDataMap {
-	fn deref_mut(&mut self) -> &mut Self::Target {
-		&mut self.0
+		&self.map
 	}
 }
 
 impl PathDataMap {
+	/// Map that evicts entries once they take up more than `max_bytes`
+	pub fn with_limit(max_bytes: usize) -> PathDataMap {
+		PathDataMap {
+			max_bytes: Some(max_bytes),
+			..Default::default()
+		}
+	}
+
+	fn tick(&self) -> u64 {
+		self.clock.set(self.clock.get() + 1);
+		self.clock.get()
+	}
+
+	/// Look up a value that is about to be shown, counting it as a hit or a miss
+	pub fn lookup(&self, path: &BrowserPath) -> Option<&PathData> {
+		let data = self.map.get(path);
+		match self.last_used.get(path) {
+			Some(last_used) => {
+				last_used.set(self.tick());
+				self.hits.set(self.hits.get() + 1);
+			}
+			None => self.misses.set(self.misses.get() + 1),
+		}
+		data
+	}
+
+	pub fn get_mut(&mut self, path: &BrowserPath) -> Option<&mut PathData> {
+		self.map.get_mut(path)
+	}
+
+	pub fn insert(&mut self, path: BrowserPath, data: PathData) {
+		self.stale.remove(&path);
+		self.bytes += entry_size(&path, &data);
+		self.last_used.insert(path.clone(), Cell::new(self.tick()));
+		if let Some(old) = self.map.insert(path.clone(), data) {
+			self.bytes -= entry_size(&path, &old);
+		}
+	}
+
+	pub fn remove(&mut self, path: &BrowserPath) -> Option<PathData> {
+		let data = self.map.remove(path)?;
+		self.last_used.remove(path);
+		self.stale.remove(path);
+		self.bytes -= entry_size(path, &data);
+		Some(data)
+	}
+
+	pub fn retain(&mut self, mut keep: impl FnMut(&BrowserPath, &PathData) -> bool) {
+		let removed = self
+			.map
+			.iter()
+			.filter(|(path, data)| !keep(path, data))
+			.map(|(path, _)| path.clone())
+			.collect::<Vec<_>>();
+		for path in removed {
+			self.remove(&path);
+		}
+	}
+
+	pub fn clear(&mut self) {
+		self.map.clear();
+		self.last_used.clear();
+		self.stale.clear();
+		self.bytes = 0;
+	}
+
+	/// Store a result from the worker, keeping the cursor of lists that are being refreshed. A
+	/// stale value stays until the actual result replaces it.
+	pub fn insert_data(&mut self, path: BrowserPath, data: PathData) {
+		if let PathData::Loading = data {
+			if self.is_stale(&path) {
+				return;
+			}
+		}
+		let old_size = self.map.get(&path).map(|x| entry_size(&path, x));
+		match (self.map.get_mut(&path), data) {
+			(Some(PathData::List(p)), PathData::List(d)) => {
+				let cursor = p.state.selected().unwrap_or(0);
+				p.state.select(Some(cursor.min(d.list.len())));
+				p.list = d.list;
+				self.stale.remove(&path);
+				self.bytes =
+					self.bytes - old_size.unwrap_or(0) + entry_size(&path, &self.map[&path]);
+				if let Some(last_used) = self.last_used.get(&path) {
+					last_used.set(self.tick());
+				}
+			}
+			(_, data) => {
+				self.insert(path, data);
+			}
+		}
+	}
+
+	/// Show a value from the disk cache until the worker confirms it
+	pub fn insert_stale(&mut self, path: BrowserPath, data: PathData) {
+		self.insert(path.clone(), data);
+		self.stale.insert(path, Cell::new(false));
+	}
+
+	pub fn is_stale(&self, path: &BrowserPath) -> bool {
+		self.stale.contains_key(path)
+	}
+
+	/// Whether `path` is stale and the worker has not been asked for it yet. Asking is up to
+	/// the caller.
+	pub fn needs_confirmation(&self, path: &BrowserPath) -> bool {
+		self.stale
+			.get(path)
+			.is_some_and(|requested| !requested.replace(true))
+	}
+
+	pub fn over_limit(&self) -> bool {
+		self.max_bytes.is_some_and(|max| self.bytes > max)
+	}
+
+	/// Drop the least recently used entries until a tenth of the limit is free again. Pinned
+	/// entries and values still being evaluated are kept. Dropped values are requested again
+	/// by the next lookup that misses them. Returns the dropped paths.
+	pub fn evict(&mut self, pinned: &HashSet<BrowserPath>) -> Vec<BrowserPath> {
+		let Some(max) = self.max_bytes.filter(|_| self.over_limit()) else {
+			return vec![];
+		};
+		let mut candidates = self
+			.map
+			.iter()
+			.filter(|(path, data)| !matches!(data, PathData::Loading) && !pinned.contains(*path))
+			.map(|(path, _)| (self.last_used[path].get(), path.clone()))
+			.collect::<Vec<_>>();
+		candidates.sort_unstable_by_key(|(last_used, _)| *last_used);
+		let mut evicted = vec![];
+		for (_, path) in candidates {
+			if self.bytes <= max - max / 10 {
+				break;
+			}
+			self.remove(&path);
+			self.evictions += 1;
+			evicted.push(path);
+		}
+		evicted
+	}
+
+	pub fn stats(&self) -> CacheStats {
+		CacheStats {
+			entries: self.map.len(),
+			bytes: self.bytes,
+			max_bytes: self.max_bytes,
+			hits: self.hits.get(),
+			misses: self.misses.get(),
+			evictions: self.evictions,
+			stale: self.stale.len(),
+		}
+	}
+
 	pub fn current_list(&self, current_path: &BrowserPath) -> Option<&ListData> {
 		self.get(current_path).and_then(|x| match x {
 			PathData::List(data) => Some(data),
@@ -91,7 +600,7 @@ impl PathDataMap {
 		})
 	}
 	pub fn current_list_mut(&mut self, current_path: &BrowserPath) -> Option<&mut ListData> {
-		self.get_mut(&current_path).and_then(|x| match x {
+		self.get_mut(current_path).and_then(|x| match x {
 			PathData::List(data) => Some(data),
 			_ => None,
 		})
@@ -138,7 +647,8 @@ impl BrowserStack {
 #[derive(Debug)]
 pub enum Message {
 	TermEvent(crossterm::event::Event),
-	Data(BrowserPath, PathData),
+	/// Result from the main worker, tagged with the generation of the worker that produced it
+	Data(usize, BrowserPath, PathData),
 	CurrentPath(BrowserPath),
 	Refresh,
 	PageDown,
@@ -154,6 +664,9 @@ pub enum Message {
 	BookmarkInput(KeyEvent),
 	CreateBookmark,
 	DeleteBookmark,
+	ToggleBookmarkScope,
+	EditBookmark(BookmarkField),
+	MoveBookmark(isize),
 	Back,
 	EnterItem,
 	ListUp,
@@ -162,6 +675,67 @@ pub enum Message {
 	SearchPrev,
 	NavigatorNext,
 	NavigatorPrev,
+	DiffData(usize, BrowserPath, PathData),
+	DiffMark,
+	DiffNext,
+	DiffPrev,
+	SystemCompareEnter,
+	EvalInputEnter,
+	EvalInputExit,
+	EvalInput(KeyEvent),
+	/// Evaluate the expression in the `:` prompt against the value under the cursor
+	Eval,
+	/// Suspend the TUI and open `nix repl` with the value under the cursor in scope
+	Repl,
+	/// Update the keys typed so far of a multi-key command
+	PendingKeys(PendingKeys),
+	/// Run an action this many times
+	Repeat(usize, Action),
+	/// Select the item at this index of the current list, clamped to the last item
+	ListGoto(usize),
+	/// Scroll the current list so the cursor is in the middle
+	CenterCursor,
+	SetMark(char),
+	JumpMark(char),
+	/// Show or hide the cache panel
+	CacheStats,
+	/// Switch the current list to the next sort mode
+	Sort,
+	/// Switch every list to the next sort mode
+	SortAll,
+	/// Evaluate every child of the current list and show how it went
+	BatchEval,
+	/// Move the cursor in the table of the batch
+	BatchMove(isize),
+	/// Go to the child under the cursor in the table of the batch
+	BatchEnter,
+	BatchExit,
+	/// Copy the path of the value under the cursor
+	CopyPath,
+	/// Copy the value under the cursor, or its JSON if it is an attribute set or list
+	CopyValue,
+	/// JSON of a subtree to copy, described by the first field
+	CopyResult(String, PathData),
+	/// A file preview read in the background, by the path it was requested for
+	FilePreview(String, FilePreview),
+	/// Respawn the workers and evaluate everything on the visit stack again
+	Reload,
+	/// Open or close the help overlay
+	Help,
+	/// Switch to the next column layout
+	Zoom,
+	/// Switch between the tree view and the columns, keeping the selected path
+	Tree,
+	/// Move the cursor of the tree view by this many rows
+	TreeMove(isize),
+	/// Expand the node under the cursor, or go to its first child if it already is
+	TreeExpand,
+	/// Collapse the node under the cursor, or go to its parent if it already is
+	TreeCollapse,
+	/// Scroll the help by this many lines
+	HelpScroll(isize),
+	/// Scroll the help by this many half pages
+	HelpPage(isize),
 	Quit,
 }
 
@@ -170,6 +744,8 @@ pub enum BrowserStackItem {
 	Root,
 	Bookmarks,
 	Recents,
+	SystemCompare,
+	Services,
 	BrowserPath(BrowserPath),
 }
 
@@ -181,7 +757,7 @@ impl Serialize for BrowserPath {
 	where
 		S: serde::Serializer,
 	{
-		serializer.collect_str(&self.to_expr())
+		serializer.collect_str(self)
 	}
 }
 
@@ -191,7 +767,12 @@ impl<'de> Deserialize<'de> for BrowserPath {
 		D: Deserializer<'de>,
 	{
 		let s = String::deserialize(deserializer)?;
-		Ok(BrowserPath::from(s))
+		let mut path = BrowserPath::from(s);
+		// Paths used to be saved without the `.` of the root in front
+		if path.0.first().is_some_and(|x| !x.is_empty()) {
+			path.0.insert(0, String::new());
+		}
+		Ok(path)
 	}
 }
 
@@ -212,73 +793,97 @@ impl BrowserPath {
 		self.0.extend_from_slice(&other.0);
 		self
 	}
+	/// The path as an attribute path relative to the root expression, for showing and copying
 	pub fn to_expr(&self) -> String {
-		let mut result = String::new();
-
-		let mut items = self.0.iter().peekable();
-
-		if let Some(0) = items.peek().map(|x| x.len()) {
-			items.next();
+		let printed = self.to_string();
+		match printed.strip_prefix('.') {
+			Some(x) if self.0.first().is_some_and(|x| x.is_empty()) => x.to_string(),
+			_ => printed,
 		}
+	}
 
-		for (i, element) in items.enumerate() {
-			if i > 0 {
-				result.push('.');
-			}
-
-			if element.contains('.') {
-				result.push('"');
-				result.push_str(element);
-				result.push('"');
-			} else {
-				result.push_str(element);
-			}
-		}
+	/// The path as the worker expects it
+	pub fn to_attr_path(&self) -> String {
+		attr_path::print_for_worker(&self.0)
+	}
+}
 
-		result
+/// Written so that parsing it with `From<String>` gives the same path back
+impl fmt::Display for BrowserPath {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "{}", attr_path::print(&self.0))
 	}
 }
 
 impl From<String> for BrowserPath {
 	fn from(value: String) -> Self {
-		let mut res = Vec::new();
-		let mut cur = String::new();
-		let mut chars = value.chars().peekable();
-
-		while let Some(c) = chars.next() {
-			match c {
-				'.' => {
-					res.push(cur);
-					cur = String::new();
-				}
-				'"' => {
-					while let Some(inner_c) = chars.next() {
-						if inner_c == '"' {
-							break;
-						}
-						cur.push(inner_c);
-					}
-				}
-				_ => cur.push(c),
-			}
-		}
-
-		res.push(cur);
-
-		BrowserPath(res)
+		BrowserPath(attr_path::lex(&value))
 	}
 }
 
 #[test]
 pub fn test_expr_conversion() {
-	let path = BrowserPath::from(".".to_string());
+	let path = BrowserPath::from("".to_string());
 	assert_eq!(path.to_expr(), "");
+	let path = BrowserPath::from(".".to_string());
+	assert_eq!(path.to_expr(), "\"\"");
 	let path = BrowserPath::from(".nixosConfigurations".to_string());
 	assert_eq!(path.to_expr(), "nixosConfigurations");
 	let path = BrowserPath::from(r#".nixosConfigurations."example.com""#.to_string());
 	assert_eq!(path.to_expr(), r#"nixosConfigurations."example.com""#);
 }
 
+#[test]
+pub fn test_path_data_eviction() {
+	let path = |x: &str| BrowserPath::from(x.to_string());
+	let value = || PathData::String("x".repeat(100));
+	let size = entry_size(&path(".a"), &value());
+	let mut path_data = PathDataMap::with_limit(size * 7 / 2);
+	for x in [".a", ".b", ".c"] {
+		path_data.insert(path(x), value());
+	}
+	assert!(!path_data.over_limit());
+	assert!(path_data.lookup(&path(".a")).is_some());
+	assert!(path_data.lookup(&path(".d")).is_none());
+
+	path_data.insert(path(".d"), value());
+	assert!(path_data.over_limit());
+	let pinned = HashSet::from([path(".b")]);
+	assert_eq!(path_data.evict(&pinned), vec![path(".c")]);
+	assert!(!path_data.over_limit());
+	assert_eq!(
+		path_data.stats(),
+		CacheStats {
+			entries: 3,
+			bytes: size * 3,
+			max_bytes: Some(size * 7 / 2),
+			hits: 1,
+			misses: 1,
+			evictions: 1,
+			stale: 0,
+		}
+	);
+
+	path_data.retain(|p, _| p != &path(".a"));
+	assert_eq!(path_data.stats().bytes, size * 2);
+}
+
+#[test]
+pub fn test_stale_until_result() {
+	let path = BrowserPath::from(".a".to_string());
+	let mut path_data = PathDataMap::default();
+	path_data.insert_stale(path.clone(), PathData::Int(1));
+	assert!(path_data.needs_confirmation(&path));
+
+	path_data.insert_data(path.clone(), PathData::Loading);
+	assert!(path_data.is_stale(&path));
+	assert!(matches!(path_data.get(&path), Some(PathData::Int(1))));
+
+	path_data.insert_data(path.clone(), PathData::Int(2));
+	assert!(!path_data.is_stale(&path));
+	assert!(matches!(path_data.get(&path), Some(PathData::Int(2))));
+}
+
 #[derive(Default, Debug, PartialEq, Eq)]
 pub enum RunningState {
 	#[default]
@@ -286,7 +891,7 @@ pub enum RunningState {
 	Stopped,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum ListType {
 	List,
 	Attrset,
@@ -370,7 +975,28 @@ impl From<NixValue> for PathData {
 	}
 }
 
+/// An evaluated attribute set with these names, for tests
+#[cfg(test)]
+pub fn attrs(names: &[&str]) -> PathData {
+	PathData::List(ListData {
+		state: ListState::default(),
+		list_type: ListType::Attrset,
+		list: names.iter().map(|x| x.to_string()).collect(),
+	})
+}
+
 impl PathData {
+	/// The path on disk this value refers to, for paths and strings holding a store path
+	pub fn file_target(&self) -> Option<&str> {
+		match self {
+			PathData::Path(p) => Some(p),
+			PathData::String(s) if s.starts_with(STORE_DIR) && !s.contains(char::is_whitespace) => {
+				Some(s)
+			}
+			_ => None,
+		}
+	}
+
 	pub fn get_type(&self) -> String {
 		match self {
 			PathData::List(data) => match data.list_type {
@@ -395,14 +1021,15 @@ impl PathData {
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct Bookmark {
-	pub display: String,
+	/// Called `display` before bookmarks could be renamed
+	#[serde(alias = "display")]
+	pub name: String,
 	pub path: BrowserPath,
-}
-
-impl<'a> Into<Text<'a>> for Bookmark {
-	fn into(self) -> Text<'a> {
-		Text::raw(self.display)
-	}
+	/// Heading the bookmark is listed under
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub group: Option<String>,
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub note: Option<String>,
 }
 
 #[derive(Debug, Default)]
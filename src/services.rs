@@ -0,0 +1,203 @@
+use ratatui::{
+	layout::Rect,
+	text::{Line, Span},
+	widgets::{List, ListItem, ListState, Paragraph},
+	Frame,
+};
+
+use crate::{
+	model::{BrowserPath, PathData, PathDataMap},
+	theme::theme,
+	view::with_selected_style,
+};
+
+/// Attribute sets below `config` whose children are listed when their `enable` is true
+pub const SCANNED: &[&str] = &["services", "programs", "virtualisation", "systemd.services"];
+
+/// Everything enabled in a NixOS configuration, reached from the root menu
+#[derive(Debug)]
+pub struct Services {
+	pub config: BrowserPath,
+	pub state: ListState,
+}
+
+/// An enabled child of one of the scanned sets
+#[derive(Debug, PartialEq, Eq)]
+pub struct ServiceEntry {
+	/// The scanned set it is in, like `services`
+	pub group: &'static str,
+	pub name: String,
+	pub path: BrowserPath,
+}
+
+impl Services {
+	pub fn new(config: BrowserPath) -> Services {
+		Services {
+			config,
+			state: ListState::default().with_selected(Some(0)),
+		}
+	}
+
+	pub fn group_paths(&self) -> Vec<BrowserPath> {
+		SCANNED.iter().map(|group| self.group_path(group)).collect()
+	}
+
+	fn group_path(&self, group: &str) -> BrowserPath {
+		group
+			.split('.')
+			.fold(self.config.clone(), |path, x| path.child(x.to_string()))
+	}
+
+	/// The `enable` option of every child of `path`, if it is one of the scanned sets. Children
+	/// without one evaluate to an error and are left out.
+	pub fn enable_paths(&self, path: &BrowserPath, path_data: &PathDataMap) -> Vec<BrowserPath> {
+		if !self.group_paths().contains(path) {
+			return vec![];
+		}
+		path_data
+			.current_list(path)
+			.map(|list| {
+				list.list
+					.iter()
+					.map(|name| path.child(name.clone()).child("enable".to_string()))
+					.collect()
+			})
+			.unwrap_or_default()
+	}
+
+	/// Enabled children of the scanned sets, in the order of `SCANNED`
+	pub fn entries(&self, path_data: &PathDataMap) -> Vec<ServiceEntry> {
+		let mut entries = vec![];
+		for group in SCANNED {
+			let group_path = self.group_path(group);
+			let Some(list) = path_data.current_list(&group_path) else {
+				continue;
+			};
+			for name in &list.list {
+				let path = group_path.child(name.clone());
+				let enable = path.child("enable".to_string());
+				if let Some(PathData::Bool(true)) = path_data.get(&enable) {
+					entries.push(ServiceEntry {
+						group,
+						name: name.clone(),
+						path,
+					});
+				}
+			}
+		}
+		entries
+	}
+
+	/// Number of `enable` options evaluated so far and the number that have to be
+	pub fn progress(&self, path_data: &PathDataMap) -> (usize, usize) {
+		let mut checked = 0;
+		let mut total = 0;
+		for group_path in self.group_paths() {
+			for enable in self.enable_paths(&group_path, path_data) {
+				total += 1;
+				if !matches!(path_data.get(&enable), None | Some(PathData::Loading)) {
+					checked += 1;
+				}
+			}
+		}
+		(checked, total)
+	}
+
+	pub fn selected_path(&self, path_data: &PathDataMap) -> Option<BrowserPath> {
+		let i = self.state.selected()?;
+		self.entries(path_data).into_iter().nth(i).map(|x| x.path)
+	}
+}
+
+pub fn render_services(
+	f: &mut Frame,
+	services: &mut Services,
+	path_data: &PathDataMap,
+	inner: Rect,
+) {
+	let entries = services.entries(path_data);
+	let group_width = SCANNED.iter().map(|x| x.len()).max().unwrap_or(0);
+	let items = entries.iter().map(|entry| {
+		ListItem::new(Line::from(vec![
+			Span::styled(format!("{:group_width$}  ", entry.group), theme().dim),
+			Span::raw(entry.name.clone()),
+		]))
+	});
+	f.render_stateful_widget(
+		with_selected_style(List::new(items)),
+		inner,
+		&mut services.state,
+	);
+}
+
+/// What is being scanned and how far along it is
+pub fn render_services_summary(
+	f: &mut Frame,
+	services: &Services,
+	path_data: &PathDataMap,
+	inner: Rect,
+) {
+	let (checked, total) = services.progress(path_data);
+	let entries = services.entries(path_data);
+	let mut lines = vec![
+		Line::styled(services.config.to_expr(), theme().title),
+		Line::default(),
+	];
+	if total == 0 || checked < total {
+		lines.push(Line::styled(
+			format!("Checking {checked} of {total} options ..."),
+			theme().dim,
+		));
+	}
+	for group in SCANNED {
+		let n = entries.iter().filter(|x| x.group == *group).count();
+		lines.push(Line::from(format!("{n} enabled in {group}")));
+	}
+	f.render_widget(Paragraph::new(lines), inner);
+}
+
+#[test]
+pub fn test_services() {
+	use crate::model::attrs;
+
+	let path = |x: &str| BrowserPath::from(x.to_string());
+	let services = Services::new(path(".config"));
+	let mut path_data = PathDataMap::default();
+	path_data.insert(
+		path(".config.services"),
+		attrs(&["nginx", "openssh", "xserver"]),
+	);
+	path_data.insert(path(".config.systemd.services"), attrs(&["backup"]));
+	path_data.insert(path(".config.services.nginx.enable"), PathData::Bool(true));
+	path_data.insert(
+		path(".config.services.openssh.enable"),
+		PathData::Bool(false),
+	);
+	path_data.insert(
+		path(".config.systemd.services.backup.enable"),
+		PathData::Bool(true),
+	);
+
+	assert_eq!(
+		services.enable_paths(&path(".config.systemd.services"), &path_data),
+		vec![path(".config.systemd.services.backup.enable")]
+	);
+	assert!(services
+		.enable_paths(&path(".config.services.nginx"), &path_data)
+		.is_empty());
+	let entries = services.entries(&path_data);
+	assert_eq!(
+		entries
+			.iter()
+			.map(|x| (x.group, x.path.to_expr()))
+			.collect::<Vec<_>>(),
+		vec![
+			("services", "config.services.nginx".to_string()),
+			(
+				"systemd.services",
+				"config.systemd.services.backup".to_string()
+			)
+		]
+	);
+	assert_eq!(services.progress(&path_data), (3, 4));
+}
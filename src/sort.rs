@@ -0,0 +1,210 @@
+use std::{cmp::Ordering, collections::HashMap};
+
+use serde::{Deserialize, Serialize};
+
+use crate::model::{BrowserPath, ListType, PathData, PathDataMap};
+
+/// Children evaluated to sort a list by their values. Larger lists are sorted by what has been
+/// evaluated so far, with the rest at the end.
+pub const SORT_PREFETCH: usize = 500;
+
+/// Order of the items of a list, cycled with `o` for the current list and `O` for all lists
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum SortMode {
+	/// As the worker lists them
+	#[default]
+	Default,
+	/// By name, with numbers compared by their value
+	Name,
+	/// Attribute sets first, then lists, strings and so on, each by name
+	Type,
+	/// Largest attribute sets, lists and strings first
+	Size,
+	ErrorsFirst,
+}
+
+impl SortMode {
+	pub fn next(self) -> SortMode {
+		match self {
+			SortMode::Default => SortMode::Name,
+			SortMode::Name => SortMode::Type,
+			SortMode::Type => SortMode::Size,
+			SortMode::Size => SortMode::ErrorsFirst,
+			SortMode::ErrorsFirst => SortMode::Default,
+		}
+	}
+
+	pub fn name(self) -> &'static str {
+		match self {
+			SortMode::Default => "default",
+			SortMode::Name => "name",
+			SortMode::Type => "type",
+			SortMode::Size => "size",
+			SortMode::ErrorsFirst => "errors first",
+		}
+	}
+
+	pub fn is_default(&self) -> bool {
+		*self == SortMode::Default
+	}
+
+	/// Whether the order depends on the values of the children
+	pub fn needs_values(self) -> bool {
+		matches!(
+			self,
+			SortMode::Type | SortMode::Size | SortMode::ErrorsFirst
+		)
+	}
+}
+
+/// The sort mode of every list, set for all of them or for single paths
+#[derive(Debug, Default)]
+pub struct SortState {
+	pub global: SortMode,
+	pub per_path: HashMap<BrowserPath, SortMode>,
+}
+
+impl SortState {
+	pub fn mode(&self, path: &BrowserPath) -> SortMode {
+		self.per_path.get(path).copied().unwrap_or(self.global)
+	}
+}
+
+/// Compare names like a person would, so `x9` comes before `x10`
+pub fn natural_cmp(a: &str, b: &str) -> Ordering {
+	let chunks = |x: &str| {
+		let mut chunks: Vec<(bool, String)> = vec![];
+		for c in x.chars() {
+			let digit = c.is_ascii_digit();
+			match chunks.last_mut() {
+				Some((last_digit, chunk)) if *last_digit == digit => chunk.push(c),
+				_ => chunks.push((digit, c.to_string())),
+			}
+		}
+		chunks
+	};
+	let (a_chunks, b_chunks) = (chunks(a), chunks(b));
+	for ((a_digit, a_chunk), (b_digit, b_chunk)) in a_chunks.iter().zip(&b_chunks) {
+		let ordering = if *a_digit && *b_digit {
+			let a_number = a_chunk.trim_start_matches('0');
+			let b_number = b_chunk.trim_start_matches('0');
+			a_number
+				.len()
+				.cmp(&b_number.len())
+				.then_with(|| a_number.cmp(b_number))
+		} else {
+			a_chunk.to_lowercase().cmp(&b_chunk.to_lowercase())
+		};
+		if ordering != Ordering::Equal {
+			return ordering;
+		}
+	}
+	a_chunks.len().cmp(&b_chunks.len()).then_with(|| a.cmp(b))
+}
+
+/// A name ordered by [`natural_cmp`], for use in sort keys
+#[derive(PartialEq, Eq)]
+struct Natural<'a>(&'a str);
+
+impl Ord for Natural<'_> {
+	fn cmp(&self, other: &Self) -> Ordering {
+		natural_cmp(self.0, other.0)
+	}
+}
+
+impl PartialOrd for Natural<'_> {
+	fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+		Some(self.cmp(other))
+	}
+}
+
+/// Position of a type in the `type` order. Values that are not evaluated yet come last.
+fn type_rank(data: Option<&PathData>) -> u8 {
+	match data {
+		Some(PathData::List(x)) if x.list_type == ListType::Attrset => 0,
+		Some(PathData::List(_)) => 1,
+		Some(PathData::String(_)) => 2,
+		Some(PathData::Path(_)) => 3,
+		Some(PathData::Int(_)) => 4,
+		Some(PathData::Float(_)) => 5,
+		Some(PathData::Bool(_)) => 6,
+		Some(PathData::Null) => 7,
+		Some(PathData::Function) => 8,
+		Some(PathData::External) => 9,
+		Some(PathData::Thunk) => 10,
+		Some(PathData::Error(_)) => 11,
+		Some(PathData::Loading) | None => 12,
+	}
+}
+
+/// Size for the `size` order, largest first. Values that are not evaluated yet come last.
+fn size_rank(data: Option<&PathData>) -> (bool, std::cmp::Reverse<usize>) {
+	let size = match data {
+		Some(PathData::List(x)) => x.list.len(),
+		Some(PathData::String(x)) | Some(PathData::Path(x)) => x.len(),
+		Some(PathData::Loading) | None => return (true, std::cmp::Reverse(0)),
+		Some(_) => 0,
+	};
+	(false, std::cmp::Reverse(size))
+}
+
+/// Sort the list at `path` in place, keeping the cursor on the same item. Does nothing for the
+/// default order, which only the worker knows.
+pub fn sort_list(path_data: &mut PathDataMap, path: &BrowserPath, mode: SortMode) {
+	let Some(list) = path_data.current_list(path) else {
+		return;
+	};
+	let child = |name: &String| path_data.get(&path.child(name.clone()));
+	let mut names = list.list.iter().collect::<Vec<_>>();
+	match mode {
+		SortMode::Default => return,
+		SortMode::Name => names.sort_by(|a, b| natural_cmp(a, b)),
+		SortMode::Type => names.sort_by_cached_key(|x| (type_rank(child(x)), Natural(x))),
+		SortMode::Size => names.sort_by_cached_key(|x| (size_rank(child(x)), Natural(x))),
+		SortMode::ErrorsFirst => names.sort_by_cached_key(|x| {
+			let is_error = matches!(child(x), Some(PathData::Error(_)));
+			(!is_error, Natural(x))
+		}),
+	}
+	let names = names.into_iter().cloned().collect::<Vec<_>>();
+	if let Some(list) = path_data.current_list_mut(path) {
+		let selected = list.state.selected().and_then(|i| list.list.get(i));
+		let cursor = selected.and_then(|x| names.iter().position(|y| y == x));
+		list.list = names;
+		if cursor.is_some() {
+			list.state.select(cursor);
+		}
+	}
+}
+
+#[test]
+pub fn test_sort_list() {
+	use crate::model::attrs;
+
+	let path = |x: &str| BrowserPath::from(x.to_string());
+	let mut path_data = PathDataMap::default();
+	path_data.insert(path(""), attrs(&["b10", "b9", "a", "err", "s"]));
+	let list = path_data.current_list_mut(&path("")).unwrap();
+	list.state.select(Some(0));
+	path_data.insert(path(".b9"), PathData::Int(1));
+	path_data.insert(path(".a"), PathData::String("abc".to_string()));
+	path_data.insert(path(".err"), PathData::Error("boom".to_string()));
+	path_data.insert(path(".s"), PathData::String("abcdef".to_string()));
+	let order = |path_data: &PathDataMap| path_data.current_list(&path("")).unwrap().list.clone();
+
+	sort_list(&mut path_data, &path(""), SortMode::Name);
+	assert_eq!(order(&path_data), ["a", "b9", "b10", "err", "s"]);
+	// The cursor stays on `b10`
+	assert_eq!(
+		path_data.current_list(&path("")).unwrap().state.selected(),
+		Some(2)
+	);
+	sort_list(&mut path_data, &path(""), SortMode::Type);
+	assert_eq!(order(&path_data), ["a", "s", "b9", "err", "b10"]);
+	sort_list(&mut path_data, &path(""), SortMode::Size);
+	assert_eq!(order(&path_data), ["s", "a", "b9", "err", "b10"]);
+	sort_list(&mut path_data, &path(""), SortMode::ErrorsFirst);
+	assert_eq!(order(&path_data), ["err", "a", "b9", "b10", "s"]);
+	assert_eq!(natural_cmp("x01", "x1"), "x01".cmp("x1"));
+}
@@ -0,0 +1,53 @@
+//! A hash for ids and keys that are saved to disk. `DefaultHasher` may change with any Rust
+//! release, which would orphan everything saved by the previous build.
+
+const OFFSET_BASIS: u64 = 0xcbf29ce484222325;
+const PRIME: u64 = 0x100000001b3;
+
+/// 64 bit FNV-1a, fed with bytes
+#[derive(Debug, Clone, Copy)]
+pub struct Fnv1a(u64);
+
+impl Default for Fnv1a {
+	fn default() -> Self {
+		Fnv1a(OFFSET_BASIS)
+	}
+}
+
+impl Fnv1a {
+	pub fn write(&mut self, bytes: &[u8]) {
+		for &byte in bytes {
+			self.0 = (self.0 ^ byte as u64).wrapping_mul(PRIME);
+		}
+	}
+
+	/// Write one of several fields, prefixed with its length so neighbouring fields can't run
+	/// together
+	pub fn write_field(&mut self, bytes: &[u8]) {
+		self.write(&(bytes.len() as u64).to_le_bytes());
+		self.write(bytes);
+	}
+
+	pub fn finish(&self) -> u64 {
+		self.0
+	}
+}
+
+pub fn fnv1a(bytes: &[u8]) -> u64 {
+	let mut hasher = Fnv1a::default();
+	hasher.write(bytes);
+	hasher.finish()
+}
+
+#[test]
+pub fn test_fnv1a() {
+	assert_eq!(fnv1a(b""), 0xcbf29ce484222325);
+	assert_eq!(fnv1a(b"a"), 0xaf63dc4c8601ec8c);
+	let fields = |a: &[u8], b: &[u8]| {
+		let mut hasher = Fnv1a::default();
+		hasher.write_field(a);
+		hasher.write_field(b);
+		hasher.finish()
+	};
+	assert_ne!(fields(b"ab", b"c"), fields(b"a", b"bc"));
+}
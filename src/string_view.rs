@@ -0,0 +1,372 @@
+use ratatui::{
+	layout::Rect,
+	style::{Style, Stylize},
+	text::{Line, Span},
+	widgets::Paragraph,
+	Frame,
+};
+
+use crate::{model::BrowserPath, theme::theme};
+
+/// Syntax used to highlight a string value, guessed from where it lives in the config
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Syntax {
+	Nix,
+	Shell,
+	Systemd,
+	Plain,
+}
+
+/// Attribute names whose value is conventionally a shell script in nixpkgs / NixOS modules
+const SHELL_ATTRS: &[&str] = &[
+	"script",
+	"preStart",
+	"postStart",
+	"preStop",
+	"postStop",
+	"reload",
+	"shellHook",
+	"shellInit",
+	"loginShellInit",
+	"interactiveShellInit",
+	"promptInit",
+	"initExtra",
+	"extraCommands",
+	"extraStopCommands",
+	"buildCommand",
+	"installPhase",
+	"buildPhase",
+	"configurePhase",
+	"checkPhase",
+	"unpackPhase",
+	"patchPhase",
+	"fixupPhase",
+	"postInstall",
+	"preInstall",
+	"postBuild",
+	"preBuild",
+	"postFixup",
+	"postPatch",
+	"activationScript",
+];
+
+const SYSTEMD_EXTENSIONS: &[&str] = &[
+	"service",
+	"socket",
+	"timer",
+	"mount",
+	"automount",
+	"path",
+	"target",
+	"slice",
+	"scope",
+	"network",
+	"netdev",
+	"link",
+];
+
+impl Syntax {
+	pub fn guess(path: &BrowserPath, content: &str) -> Syntax {
+		if let Some(first) = content.lines().next() {
+			if let Some(interpreter) = first.strip_prefix("#!") {
+				return if interpreter.contains("nix-shell")
+					|| ["sh", "bash", "zsh", "dash", "ash"].iter().any(|sh| {
+						interpreter
+							.split(|c: char| c == '/' || c.is_whitespace())
+							.any(|x| x == *sh)
+					}) {
+					Syntax::Shell
+				} else {
+					Syntax::Plain
+				};
+			}
+		}
+
+		let mut names = path.0.iter().rev();
+		let name = names.next().map(|x| x.as_str()).unwrap_or("");
+		// `environment.etc."foo.conf".text`, `systemd.units."foo.service".text` etc. carry the
+		// interesting part of the name one level up
+		let file_name = match name {
+			"text" | "source" | "content" => names.next().map(|x| x.as_str()).unwrap_or(""),
+			_ => name,
+		};
+
+		if SHELL_ATTRS.contains(&name) {
+			return Syntax::Shell;
+		}
+		if let Some((_, ext)) = file_name.rsplit_once('.') {
+			match ext {
+				"nix" => return Syntax::Nix,
+				"sh" | "bash" | "zsh" => return Syntax::Shell,
+				x if SYSTEMD_EXTENSIONS.contains(&x) => return Syntax::Systemd,
+				_ => {}
+			}
+		}
+
+		let first_line = content.lines().map(str::trim).find(|x| !x.is_empty());
+		match first_line {
+			Some(x) if x.starts_with('[') && x.ends_with(']') => Syntax::Systemd,
+			_ => Syntax::Plain,
+		}
+	}
+
+	pub fn name(&self) -> &'static str {
+		match self {
+			Syntax::Nix => "nix",
+			Syntax::Shell => "shell",
+			Syntax::Systemd => "systemd",
+			Syntax::Plain => "text",
+		}
+	}
+
+	pub fn highlight<'a>(&self, line: &'a str) -> Line<'a> {
+		match self {
+			Syntax::Nix => highlight_code(line, NIX_KEYWORDS, false),
+			Syntax::Shell => highlight_code(line, SHELL_KEYWORDS, true),
+			Syntax::Systemd => highlight_systemd(line),
+			Syntax::Plain => Line::raw(line),
+		}
+	}
+}
+
+const NIX_KEYWORDS: &[&str] = &[
+	"let", "in", "with", "rec", "inherit", "if", "then", "else", "assert", "or", "import", "true",
+	"false", "null",
+];
+
+const SHELL_KEYWORDS: &[&str] = &[
+	"if", "then", "else", "elif", "fi", "for", "while", "until", "do", "done", "case", "esac",
+	"in", "function", "return", "export", "local", "set", "exec", "source",
+];
+
+fn comment_style() -> Style {
+	theme().comment
+}
+
+fn string_style() -> Style {
+	theme().string
+}
+
+fn keyword_style() -> Style {
+	theme().keyword
+}
+
+fn number_style() -> Style {
+	theme().number
+}
+
+fn variable_style() -> Style {
+	theme().variable
+}
+
+/// Single-line tokenizer shared by nix and shell, which agree on `#` comments and quoting
+fn highlight_code<'a>(line: &'a str, keywords: &[&str], shell_vars: bool) -> Line<'a> {
+	let mut spans = Vec::new();
+	let bytes = line.as_bytes();
+	let mut plain_start = 0;
+	let mut i = 0;
+
+	let flush = |spans: &mut Vec<Span<'a>>, from: usize, to: usize| {
+		if to > from {
+			spans.push(Span::raw(&line[from..to]));
+		}
+	};
+
+	while i < bytes.len() {
+		let c = bytes[i];
+		let at_word_start = i == 0 || !is_word_byte(bytes[i - 1]);
+		if c == b'#' && (i == 0 || bytes[i - 1].is_ascii_whitespace()) {
+			flush(&mut spans, plain_start, i);
+			spans.push(Span::styled(&line[i..], comment_style()));
+			return Line::from(spans);
+		} else if c == b'"' || c == b'\'' {
+			flush(&mut spans, plain_start, i);
+			let end = find_string_end(bytes, i + 1, c);
+			spans.push(Span::styled(&line[i..end], string_style()));
+			i = end;
+			plain_start = i;
+		} else if shell_vars && c == b'$' {
+			flush(&mut spans, plain_start, i);
+			let end = if bytes.get(i + 1) == Some(&b'{') {
+				line[i..]
+					.find('}')
+					.map(|x| i + x + 1)
+					.unwrap_or(bytes.len())
+			} else {
+				let mut end = i + 1;
+				while end < bytes.len() && is_word_byte(bytes[end]) {
+					end += 1;
+				}
+				end
+			};
+			spans.push(Span::styled(&line[i..end], variable_style()));
+			i = end;
+			plain_start = i;
+		} else if at_word_start && is_word_byte(c) {
+			let mut end = i;
+			while end < bytes.len() && (is_word_byte(bytes[end]) || bytes[end] == b'-') {
+				end += 1;
+			}
+			let word = &line[i..end];
+			let style = if keywords.contains(&word) {
+				Some(keyword_style())
+			} else if word.bytes().all(|x| x.is_ascii_digit()) {
+				Some(number_style())
+			} else {
+				None
+			};
+			if let Some(style) = style {
+				flush(&mut spans, plain_start, i);
+				spans.push(Span::styled(word, style));
+				plain_start = end;
+			}
+			i = end;
+		} else {
+			i += 1;
+		}
+	}
+	flush(&mut spans, plain_start, bytes.len());
+
+	Line::from(spans)
+}
+
+fn highlight_systemd(line: &str) -> Line<'_> {
+	let trimmed = line.trim_start();
+	if trimmed.starts_with('#') || trimmed.starts_with(';') {
+		Line::from(Span::styled(line, comment_style()))
+	} else if trimmed.starts_with('[') {
+		Line::from(Span::styled(line, keyword_style().bold()))
+	} else if let Some((key, value)) = line.split_once('=') {
+		Line::from(vec![
+			Span::styled(key, variable_style()),
+			Span::raw("="),
+			Span::raw(value),
+		])
+	} else {
+		Line::raw(line)
+	}
+}
+
+fn is_word_byte(c: u8) -> bool {
+	c.is_ascii_alphanumeric() || c == b'_'
+}
+
+/// Returns the index just past the closing quote, or the end of the line if it is unterminated
+fn find_string_end(bytes: &[u8], mut i: usize, quote: u8) -> usize {
+	while i < bytes.len() {
+		match bytes[i] {
+			b'\\' if quote == b'"' => i += 2,
+			x if x == quote => return i + 1,
+			_ => i += 1,
+		}
+	}
+	bytes.len()
+}
+
+/// Index of the first line containing `needle`, searching from `from` in the given direction
+/// and wrapping around the end of the string
+pub fn find_line(content: &str, needle: &str, from: usize, forward: bool) -> Option<usize> {
+	if needle.is_empty() {
+		return None;
+	}
+	let lines: Vec<&str> = content.lines().collect();
+	let len = lines.len();
+	(0..len)
+		.map(|offset| {
+			if forward {
+				(from + offset) % len
+			} else {
+				(from + len - offset % len) % len
+			}
+		})
+		.find(|&i| lines[i].contains(needle))
+}
+
+pub fn line_count(content: &str) -> usize {
+	content.lines().count().max(1)
+}
+
+/// Render a string with line numbers, without any escaping, starting at line `scroll`
+pub fn render_string_view(
+	f: &mut Frame,
+	content: &str,
+	syntax: Syntax,
+	scroll: usize,
+	search: Option<&str>,
+	inner: Rect,
+) {
+	let gutter_width = line_count(content).to_string().len();
+	let search = search.filter(|x| !x.is_empty());
+	let lines: Vec<Line> = content
+		.lines()
+		.enumerate()
+		.skip(scroll)
+		.take(inner.height as usize)
+		.map(|(i, line)| {
+			let mut highlighted = match search {
+				Some(needle) if line.contains(needle) => highlight_search(line, needle),
+				_ => syntax.highlight(line),
+			};
+			highlighted.spans.insert(
+				0,
+				Span::styled(format!("{:>gutter_width$} │ ", i + 1), theme().dim),
+			);
+			highlighted
+		})
+		.collect();
+
+	f.render_widget(Paragraph::new(lines), inner);
+}
+
+fn highlight_search<'a>(line: &'a str, needle: &'a str) -> Line<'a> {
+	let mut spans = Vec::new();
+	let mut last_index = 0;
+	for (index, _) in line.match_indices(needle) {
+		if index > last_index {
+			spans.push(Span::raw(&line[last_index..index]));
+		}
+		spans.push(Span::styled(needle, theme().search_match));
+		last_index = index + needle.len();
+	}
+	if last_index < line.len() {
+		spans.push(Span::raw(&line[last_index..]));
+	}
+	Line::from(spans)
+}
+
+#[test]
+pub fn test_syntax_guess() {
+	let path = |s: &str| BrowserPath::from(s.to_string());
+	assert_eq!(
+		Syntax::guess(&path(".config.systemd.services.foo.script"), "echo hi"),
+		Syntax::Shell
+	);
+	assert_eq!(
+		Syntax::guess(&path(r#".config.environment.etc."foo.nix".text"#), "{ }"),
+		Syntax::Nix
+	);
+	assert_eq!(
+		Syntax::guess(
+			&path(r#".config.systemd.units."foo.service".text"#),
+			"[Unit]\nDescription=foo"
+		),
+		Syntax::Systemd
+	);
+	assert_eq!(
+		Syntax::guess(&path(".config.foo.bar"), "#!/usr/bin/env bash\necho"),
+		Syntax::Shell
+	);
+	assert_eq!(
+		Syntax::guess(&path(".config.foo.bar"), "hello"),
+		Syntax::Plain
+	);
+}
+
+#[test]
+pub fn test_find_line() {
+	let content = "a\nfoo\nb\nfoo";
+	assert_eq!(find_line(content, "foo", 0, true), Some(1));
+	assert_eq!(find_line(content, "foo", 2, true), Some(3));
+	assert_eq!(find_line(content, "foo", 0, false), Some(3));
+	assert_eq!(find_line(content, "baz", 0, true), None);
+}
@@ -0,0 +1,273 @@
+use std::{fs, path::Path};
+
+use ratatui::{
+	layout::Rect,
+	style::{Style, Stylize},
+	text::{Line, Span},
+	widgets::{List, ListItem, ListState, Paragraph, Wrap},
+	Frame,
+};
+
+use crate::{
+	file_preview::STORE_DIR,
+	model::{BrowserPath, PathData, PathDataMap},
+	theme::theme,
+};
+
+pub const CURRENT_SYSTEM: &str = "/run/current-system";
+
+/// A part of the system closure that is linked from the toplevel derivation, together with the
+/// option that produces it
+pub struct SystemComponent {
+	pub name: &'static str,
+	/// Attribute path below `config` whose `outPath` ends up in the toplevel
+	pub attr: &'static str,
+	/// Name of the symlink inside the toplevel, empty for the toplevel itself
+	pub link: &'static str,
+}
+
+pub const COMPONENTS: &[SystemComponent] = &[
+	SystemComponent {
+		name: "system",
+		attr: "system.build.toplevel",
+		link: "",
+	},
+	SystemComponent {
+		name: "etc",
+		attr: "system.build.etc",
+		link: "etc",
+	},
+	SystemComponent {
+		name: "system packages",
+		attr: "system.path",
+		link: "sw",
+	},
+	SystemComponent {
+		name: "kernel",
+		attr: "system.build.kernel",
+		link: "kernel",
+	},
+	SystemComponent {
+		name: "kernel modules",
+		attr: "system.modulesTree",
+		link: "kernel-modules",
+	},
+	SystemComponent {
+		name: "initrd",
+		attr: "system.build.initialRamdisk",
+		link: "initrd",
+	},
+	SystemComponent {
+		name: "systemd",
+		attr: "systemd.package",
+		link: "systemd",
+	},
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ComponentStatus {
+	Same,
+	Changed,
+	Evaluating,
+	/// There is no running system to compare against, e.g. when inspecting another host
+	NotLive,
+	Error(String),
+}
+
+impl ComponentStatus {
+	pub fn label(&self) -> &str {
+		match self {
+			ComponentStatus::Same => "unchanged",
+			ComponentStatus::Changed => "changed",
+			ComponentStatus::Evaluating => "evaluating",
+			ComponentStatus::NotLive => "not in running system",
+			ComponentStatus::Error(_) => "error",
+		}
+	}
+
+	pub fn style(&self) -> Style {
+		let theme = theme();
+		match self {
+			ComponentStatus::Same => theme.ok,
+			ComponentStatus::Changed => theme.changed,
+			ComponentStatus::Evaluating | ComponentStatus::NotLive => theme.dim,
+			ComponentStatus::Error(_) => theme.error,
+		}
+	}
+}
+
+/// The running system's store paths, compared against the outputs of an evaluated `config`
+#[derive(Debug)]
+pub struct SystemCompare {
+	pub config: BrowserPath,
+	/// Store path of each entry of `COMPONENTS` in the running system
+	pub live: Vec<Option<String>>,
+	pub state: ListState,
+}
+
+impl SystemCompare {
+	pub fn new(config: BrowserPath) -> SystemCompare {
+		let live = COMPONENTS
+			.iter()
+			.map(|component| {
+				let link = Path::new(CURRENT_SYSTEM).join(component.link);
+				let target = if component.link.is_empty() {
+					fs::canonicalize(link)
+				} else {
+					fs::read_link(link)
+				};
+				target
+					.ok()
+					.and_then(|x| store_path_root(&x.to_string_lossy()))
+			})
+			.collect();
+		SystemCompare {
+			config,
+			live,
+			state: ListState::default().with_selected(Some(0)),
+		}
+	}
+
+	pub fn attr_path(&self, i: usize) -> BrowserPath {
+		COMPONENTS[i]
+			.attr
+			.split('.')
+			.fold(self.config.clone(), |path, x| path.child(x.to_string()))
+	}
+
+	pub fn out_path(&self, i: usize) -> BrowserPath {
+		self.attr_path(i).child("outPath".to_string())
+	}
+
+	pub fn out_paths(&self) -> Vec<BrowserPath> {
+		(0..COMPONENTS.len()).map(|i| self.out_path(i)).collect()
+	}
+
+	pub fn selected_path(&self) -> Option<BrowserPath> {
+		self.state.selected().map(|i| self.attr_path(i))
+	}
+
+	pub fn evaluated(&self, i: usize, path_data: &PathDataMap) -> Option<String> {
+		match path_data.get(&self.out_path(i)) {
+			Some(PathData::String(x)) | Some(PathData::Path(x)) => store_path_root(x),
+			_ => None,
+		}
+	}
+
+	pub fn status(&self, i: usize, path_data: &PathDataMap) -> ComponentStatus {
+		let live = match &self.live[i] {
+			Some(x) => x,
+			None => return ComponentStatus::NotLive,
+		};
+		match path_data.get(&self.out_path(i)) {
+			Some(PathData::Error(e)) => ComponentStatus::Error(e.clone()),
+			Some(PathData::String(_)) | Some(PathData::Path(_)) => {
+				if self.evaluated(i, path_data).as_ref() == Some(live) {
+					ComponentStatus::Same
+				} else {
+					ComponentStatus::Changed
+				}
+			}
+			_ => ComponentStatus::Evaluating,
+		}
+	}
+
+	/// One line summary of whether switching to the evaluated config would do anything
+	pub fn summary(&self, path_data: &PathDataMap) -> String {
+		match self.status(0, path_data) {
+			ComponentStatus::Same => {
+				"Up to date: a rebuild would not change the system".to_string()
+			}
+			ComponentStatus::Changed => {
+				let changed: Vec<&str> = (1..COMPONENTS.len())
+					.filter(|&i| self.status(i, path_data) == ComponentStatus::Changed)
+					.map(|i| COMPONENTS[i].name)
+					.collect();
+				if changed.is_empty() {
+					"A rebuild would create a new generation".to_string()
+				} else {
+					format!(
+						"A rebuild would create a new generation, changing {}",
+						changed.join(", ")
+					)
+				}
+			}
+			ComponentStatus::Evaluating => format!("Evaluating {} ...", self.config.to_expr()),
+			ComponentStatus::NotLive => format!("{CURRENT_SYSTEM} does not exist"),
+			ComponentStatus::Error(e) => e,
+		}
+	}
+}
+
+/// `/nix/store/<hash>-<name>` of a path somewhere inside the store
+pub fn store_path_root(path: &str) -> Option<String> {
+	let rest = path.strip_prefix(STORE_DIR)?;
+	let name = rest.split('/').next().filter(|x| !x.is_empty())?;
+	Some(format!("{STORE_DIR}{name}"))
+}
+
+pub fn render_system_compare(
+	f: &mut Frame,
+	compare: &mut SystemCompare,
+	path_data: &PathDataMap,
+	inner: Rect,
+) {
+	let items: Vec<ListItem> = (0..COMPONENTS.len())
+		.map(|i| {
+			let status = compare.status(i, path_data);
+			ListItem::new(Line::from(vec![
+				Span::raw(format!("{:<16}", COMPONENTS[i].name)),
+				Span::styled(status.label().to_string(), status.style()),
+			]))
+		})
+		.collect();
+	f.render_stateful_widget(
+		List::new(items)
+			.highlight_symbol(">>")
+			.highlight_style(theme().selected),
+		inner,
+		&mut compare.state,
+	);
+}
+
+/// Summary and both store paths of the selected component
+pub fn render_system_compare_preview(
+	f: &mut Frame,
+	compare: &SystemCompare,
+	path_data: &PathDataMap,
+	inner: Rect,
+) {
+	let i = compare.state.selected().unwrap_or(0);
+	let mut lines = vec![
+		Line::from(compare.summary(path_data)).bold(),
+		Line::default(),
+		Line::styled(format!("config.{}", COMPONENTS[i].attr), theme().title),
+		Line::default(),
+		Line::styled("evaluated:", theme().dim),
+		Line::from(
+			compare
+				.evaluated(i, path_data)
+				.unwrap_or_else(|| "-".to_string()),
+		),
+		Line::styled("running:", theme().dim),
+		Line::from(compare.live[i].clone().unwrap_or_else(|| "-".to_string())),
+	];
+	if let ComponentStatus::Error(e) = compare.status(i, path_data) {
+		lines.push(Line::default());
+		lines.push(Line::styled(e, theme().error));
+	}
+	f.render_widget(Paragraph::new(lines).wrap(Wrap { trim: false }), inner);
+}
+
+#[test]
+pub fn test_store_path_root() {
+	assert_eq!(
+		store_path_root("/nix/store/abc-linux-6.6/bzImage"),
+		Some("/nix/store/abc-linux-6.6".to_string())
+	);
+	assert_eq!(
+		store_path_root("/nix/store/abc-etc"),
+		Some("/nix/store/abc-etc".to_string())
+	);
+	assert_eq!(store_path_root("/etc/static"), None);
+}
@@ -0,0 +1,250 @@
+use std::sync::OnceLock;
+
+use ratatui::style::{Color, Modifier, Style, Stylize};
+use serde::{Deserialize, Serialize};
+
+/// The `theme` section of `config.json`
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ThemeConfig {
+	/// One of [`THEMES`]
+	pub name: String,
+}
+
+impl Default for ThemeConfig {
+	fn default() -> Self {
+		ThemeConfig {
+			name: "dark".to_string(),
+		}
+	}
+}
+
+impl ThemeConfig {
+	pub fn is_default(&self) -> bool {
+		*self == ThemeConfig::default()
+	}
+}
+
+pub const THEMES: &[&str] = &["dark", "light", "high-contrast"];
+
+/// Every style used to draw the UI
+#[derive(Debug, Clone)]
+pub struct Theme {
+	/// Item under the cursor
+	pub selected: Style,
+	/// Items matching the path navigator's tab completion
+	pub completion: Style,
+	pub search_match: Style,
+	/// Keys in the footer and the pending keys indicator
+	pub key: Style,
+	pub title: Style,
+	pub heading: Style,
+	/// Status line and inputs at the bottom
+	pub text: Style,
+	/// Line numbers, action names and other secondary text
+	pub dim: Style,
+	/// Keys in the help overlay
+	pub help_key: Style,
+	pub directory: Style,
+	pub symlink: Style,
+
+	pub ok: Style,
+	pub warning: Style,
+	pub error: Style,
+
+	pub added: Style,
+	pub removed: Style,
+	pub changed: Style,
+
+	pub list: Style,
+	pub number: Style,
+	pub string: Style,
+	pub path: Style,
+	pub bool: Style,
+	pub function: Style,
+	pub thunk: Style,
+
+	pub comment: Style,
+	pub keyword: Style,
+	pub variable: Style,
+}
+
+impl Theme {
+	pub fn dark() -> Theme {
+		Theme {
+			selected: Style::new().black().on_yellow(),
+			completion: Style::new().black().on_green(),
+			search_match: Style::new().black().on_blue(),
+			key: Style::new().black().on_gray(),
+			title: Style::new().blue(),
+			heading: Style::new().bold().underlined(),
+			text: Style::new().gray(),
+			dim: Style::new().dark_gray(),
+			help_key: Style::new().cyan(),
+			directory: Style::new().blue(),
+			symlink: Style::new().cyan(),
+			ok: Style::new().green(),
+			warning: Style::new().yellow(),
+			error: Style::new().red(),
+			added: Style::new().green(),
+			removed: Style::new().red(),
+			changed: Style::new().yellow(),
+			list: Style::new().cyan(),
+			number: Style::new().light_blue(),
+			string: Style::new().light_red(),
+			path: Style::new().fg(Color::Rgb(187, 159, 252)),
+			bool: Style::new().green(),
+			function: Style::new().magenta(),
+			thunk: Style::new().light_magenta(),
+			comment: Style::new().dark_gray().italic(),
+			keyword: Style::new().magenta(),
+			variable: Style::new().yellow(),
+		}
+	}
+
+	/// For terminals with a light background, avoiding the pale light and yellow colours
+	pub fn light() -> Theme {
+		let amber = Color::Rgb(160, 100, 0);
+		Theme {
+			selected: Style::new().white().on_blue(),
+			completion: Style::new().black().on_light_green(),
+			search_match: Style::new().black().on_light_yellow(),
+			key: Style::new().white().on_dark_gray(),
+			title: Style::new().blue(),
+			text: Style::new(),
+			help_key: Style::new().blue(),
+			directory: Style::new().blue(),
+			symlink: Style::new().magenta(),
+			warning: Style::new().fg(amber),
+			changed: Style::new().fg(amber),
+			list: Style::new().blue(),
+			number: Style::new().fg(Color::Rgb(0, 90, 180)),
+			string: Style::new().red(),
+			path: Style::new().fg(Color::Rgb(110, 60, 190)),
+			thunk: Style::new().magenta(),
+			variable: Style::new().fg(amber),
+			..Theme::dark()
+		}
+	}
+
+	pub fn high_contrast() -> Theme {
+		Theme {
+			selected: Style::new().black().on_white().bold(),
+			completion: Style::new().black().on_light_green().bold(),
+			search_match: Style::new().black().on_light_yellow().bold(),
+			key: Style::new().black().on_white(),
+			title: Style::new().white().bold(),
+			text: Style::new().white(),
+			dim: Style::new().gray(),
+			help_key: Style::new().light_cyan(),
+			directory: Style::new().light_blue().bold(),
+			symlink: Style::new().light_cyan(),
+			ok: Style::new().light_green(),
+			warning: Style::new().light_yellow(),
+			error: Style::new().light_red().bold(),
+			added: Style::new().light_green(),
+			removed: Style::new().light_red(),
+			changed: Style::new().light_yellow(),
+			list: Style::new().light_cyan(),
+			bool: Style::new().light_green(),
+			function: Style::new().light_magenta(),
+			comment: Style::new().gray().italic(),
+			keyword: Style::new().light_magenta(),
+			variable: Style::new().light_yellow(),
+			..Theme::dark()
+		}
+	}
+
+	/// The built-in theme called `name`, without colours if `no_color` is set, as asked for by
+	/// the `NO_COLOR` environment variable. Unknown names fall back to the dark theme and are
+	/// returned as a problem to report.
+	pub fn new(config: &ThemeConfig, no_color: bool) -> (Theme, Option<String>) {
+		let (theme, problem) = match config.name.as_str() {
+			"dark" => (Theme::dark(), None),
+			"light" => (Theme::light(), None),
+			"high-contrast" => (Theme::high_contrast(), None),
+			name => (
+				Theme::dark(),
+				Some(format!(
+					"unknown theme {name}, expected one of {}",
+					THEMES.join(", ")
+				)),
+			),
+		};
+		if no_color {
+			return (theme.without_colors(), problem);
+		}
+		(theme, problem)
+	}
+
+	/// Only keep modifiers like bold, with reversed video standing in for highlighted
+	/// backgrounds
+	fn without_colors(self) -> Theme {
+		let strip = |x: Style| Style::new().add_modifier(x.add_modifier);
+		let reversed = |x: Style| strip(x).add_modifier(Modifier::REVERSED);
+		Theme {
+			selected: reversed(self.selected),
+			completion: reversed(self.completion).underlined(),
+			search_match: reversed(self.search_match),
+			key: reversed(self.key),
+			title: strip(self.title).bold(),
+			text: strip(self.text),
+			dim: strip(self.dim),
+			help_key: strip(self.help_key).bold(),
+			directory: strip(self.directory).bold(),
+			symlink: strip(self.symlink).italic(),
+			ok: strip(self.ok),
+			warning: strip(self.warning),
+			error: strip(self.error).bold(),
+			added: strip(self.added),
+			removed: strip(self.removed),
+			changed: strip(self.changed),
+			list: strip(self.list),
+			number: strip(self.number),
+			string: strip(self.string),
+			path: strip(self.path),
+			bool: strip(self.bool),
+			function: strip(self.function),
+			thunk: strip(self.thunk),
+			comment: strip(self.comment),
+			keyword: strip(self.keyword),
+			variable: strip(self.variable),
+			heading: self.heading,
+		}
+	}
+}
+
+/// Whether colours are turned off with a non-empty `NO_COLOR`, see https://no-color.org
+pub fn no_color() -> bool {
+	std::env::var_os("NO_COLOR").is_some_and(|x| !x.is_empty())
+}
+
+static THEME: OnceLock<Theme> = OnceLock::new();
+
+/// Use `theme` for the rest of the program. Only the first call has an effect.
+pub fn set_theme(theme: Theme) {
+	let _ = THEME.set(theme);
+}
+
+/// The theme in use, dark unless another one was set
+pub fn theme() -> &'static Theme {
+	THEME.get_or_init(Theme::dark)
+}
+
+#[test]
+pub fn test_theme() {
+	for name in THEMES {
+		let config = ThemeConfig {
+			name: name.to_string(),
+		};
+		assert_eq!(Theme::new(&config, false).1, None);
+	}
+	let config = ThemeConfig {
+		name: "solarized".to_string(),
+	};
+	let (theme, problem) = Theme::new(&config, true);
+	assert!(problem.is_some());
+	for style in [theme.selected, theme.string, theme.path, theme.error] {
+		assert_eq!((style.fg, style.bg), (None, None));
+	}
+	assert!(theme.selected.add_modifier.contains(Modifier::REVERSED));
+}
@@ -0,0 +1,197 @@
+use std::collections::HashSet;
+
+use ratatui::{
+	layout::Rect,
+	text::{Line, Span},
+	widgets::{Block, Borders, List, ListState},
+	Frame,
+};
+
+use crate::{
+	model::{BrowserPath, ListType, PathData, PathDataMap},
+	theme::theme,
+	view::{style_from_type, with_selected_style},
+};
+
+/// Children requested when a node is expanded. Further ones are requested as the cursor
+/// reaches them.
+pub const TREE_PREFETCH: usize = 100;
+
+/// The tree view, toggled with `t`, showing everything below `root` with expandable nodes
+#[derive(Debug)]
+pub struct TreeState {
+	pub root: BrowserPath,
+	pub selected: BrowserPath,
+	/// Expanded nodes. Collapsing a node keeps its children in the `PathDataMap`.
+	pub expanded: HashSet<BrowserPath>,
+	pub list_state: ListState,
+}
+
+/// A visible node of the tree
+#[derive(Debug, PartialEq, Eq)]
+pub struct TreeRow {
+	pub path: BrowserPath,
+	pub depth: usize,
+}
+
+impl TreeState {
+	/// Tree of the root expression (or evaluation result) containing `selected`, expanded down
+	/// to it
+	pub fn new(selected: BrowserPath) -> TreeState {
+		let root = BrowserPath(selected.0.iter().take(1).cloned().collect());
+		let root = if root.0.is_empty() {
+			BrowserPath::from(String::new())
+		} else {
+			root
+		};
+		let expanded = (root.0.len()..selected.0.len())
+			.map(|i| BrowserPath(selected.0[..i].to_vec()))
+			.collect();
+		TreeState {
+			root,
+			selected,
+			expanded,
+			list_state: ListState::default(),
+		}
+	}
+
+	pub fn rows(&self, path_data: &PathDataMap) -> Vec<TreeRow> {
+		let mut rows = vec![];
+		let mut stack = vec![(self.root.clone(), 0)];
+		while let Some((path, depth)) = stack.pop() {
+			if self.expanded.contains(&path) {
+				if let Some(list) = path_data.current_list(&path) {
+					stack.extend(
+						list.list
+							.iter()
+							.rev()
+							.map(|x| (path.child(x.to_string()), depth + 1)),
+					);
+				}
+			}
+			rows.push(TreeRow { path, depth });
+		}
+		rows
+	}
+
+	/// Move the cursor by `delta` rows
+	pub fn move_by(&mut self, path_data: &PathDataMap, delta: isize) {
+		let rows = self.rows(path_data);
+		let i = rows
+			.iter()
+			.position(|x| x.path == self.selected)
+			.unwrap_or(0);
+		let i = i.saturating_add_signed(delta).min(rows.len() - 1);
+		self.selected = rows[i].path.clone();
+	}
+}
+
+/// Short form of a value shown next to its name
+fn inline_value(data: &PathData) -> String {
+	match data {
+		PathData::List(list) => {
+			let n = list.list.len();
+			let s = if n == 1 { "" } else { "s" };
+			match list.list_type {
+				ListType::Attrset => format!("{{ {n} attribute{s} }}"),
+				ListType::List => format!("[ {n} item{s} ]"),
+			}
+		}
+		PathData::String(x) => {
+			let first_line = x.lines().next().unwrap_or_default();
+			let ellipsis = if first_line.len() < x.len() {
+				"…"
+			} else {
+				""
+			};
+			format!("{first_line:?}{ellipsis}")
+		}
+		PathData::Path(x) => x.clone(),
+		PathData::Error(e) => e.lines().next().unwrap_or_default().to_string(),
+		PathData::Loading => "…".to_string(),
+		x => x.to_string().to_lowercase(),
+	}
+}
+
+pub fn render_tree(
+	f: &mut Frame,
+	tree: &mut TreeState,
+	path_data: &PathDataMap,
+	rect: Rect,
+) -> Rect {
+	let rows = tree.rows(path_data);
+	tree.list_state
+		.select(rows.iter().position(|x| x.path == tree.selected));
+	let expanded = &tree.expanded;
+	let items = rows.iter().map(|row| {
+		let data = path_data.get(&row.path);
+		let marker = match data {
+			Some(PathData::List(_)) if expanded.contains(&row.path) => "▾ ",
+			Some(PathData::List(_)) => "▸ ",
+			_ => "  ",
+		};
+		let name = match row.depth {
+			0 if row.path.to_expr().is_empty() => "root".to_string(),
+			0 => row.path.to_expr(),
+			_ => row.path.0.last().cloned().unwrap_or_default(),
+		};
+		let mut spans = vec![Span::raw(format!(
+			"{}{marker}{name}",
+			"  ".repeat(row.depth)
+		))];
+		if let Some(data) = data {
+			let style = match data {
+				PathData::List(_) => theme().dim,
+				x => style_from_type(x),
+			};
+			spans.push(Span::styled(" = ", theme().dim));
+			spans.push(Span::styled(inline_value(data), style));
+		}
+		Line::from(spans)
+	});
+	let list = with_selected_style(List::new(items));
+	let block = Block::default()
+		.borders(Borders::ALL)
+		.title("Tree")
+		.title_style(theme().title);
+	let inner = block.inner(rect);
+	f.render_widget(block, rect);
+	f.render_stateful_widget(list, inner, &mut tree.list_state);
+	inner
+}
+
+#[test]
+pub fn test_tree_rows() {
+	use crate::model::attrs;
+
+	let path = |x: &str| BrowserPath::from(x.to_string());
+	let mut path_data = PathDataMap::default();
+	path_data.insert(path(""), attrs(&["a", "b"]));
+	path_data.insert(path(".a"), attrs(&["x", "y"]));
+	path_data.insert(path(".b"), attrs(&["z"]));
+
+	let mut tree = TreeState::new(path(".a.y"));
+	let rows = |tree: &TreeState| {
+		tree.rows(&path_data)
+			.into_iter()
+			.map(|x| (x.path.to_expr(), x.depth))
+			.collect::<Vec<_>>()
+	};
+	let row = |x: &str, depth| (x.to_string(), depth);
+	assert_eq!(
+		rows(&tree),
+		vec![
+			row("", 0),
+			row("a", 1),
+			row("a.x", 2),
+			row("a.y", 2),
+			row("b", 1)
+		]
+	);
+	tree.move_by(&path_data, 1);
+	assert_eq!(tree.selected, path(".b"));
+	tree.move_by(&path_data, 10);
+	assert_eq!(tree.selected, path(".b"));
+	tree.expanded.remove(&path(".a"));
+	assert_eq!(rows(&tree), vec![row("", 0), row("a", 1), row("b", 1)]);
+}
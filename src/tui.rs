@@ -1,14 +1,59 @@
 use crossterm::{
-	terminal::{disable_raw_mode, LeaveAlternateScreen},
+	event::{DisableMouseCapture, EnableMouseCapture},
+	terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
 	ExecutableCommand,
 };
-use std::{io::stdout, panic};
+use std::{
+	io::{self, stdout},
+	panic,
+	process::{Command, ExitStatus},
+	sync::atomic::Ordering,
+	time::Duration,
+};
+
+use crate::key_handler::KEY_HANDLER_PAUSED;
 
 pub fn install_panic_hook() {
 	let original_hook = panic::take_hook();
 	panic::set_hook(Box::new(move |panic_info| {
+		stdout().execute(DisableMouseCapture).unwrap();
 		stdout().execute(LeaveAlternateScreen).unwrap();
 		disable_raw_mode().unwrap();
 		original_hook(panic_info);
 	}));
 }
+
+/// Hand the terminal over to `command` until it exits, printing `banner` first. If the command
+/// fails, its output is kept on screen until enter is pressed. The caller has to redraw everything
+/// afterwards.
+pub fn run_suspended(command: &mut Command, banner: &str) -> io::Result<ExitStatus> {
+	KEY_HANDLER_PAUSED.store(true, Ordering::SeqCst);
+	// Let the key handler finish its current poll
+	std::thread::sleep(Duration::from_millis(150));
+	stdout().execute(DisableMouseCapture)?;
+	stdout().execute(LeaveAlternateScreen)?;
+	disable_raw_mode()?;
+
+	println!("{banner}");
+	let status = command.status();
+	let failed = match &status {
+		Ok(status) => !status.success(),
+		Err(e) => {
+			eprintln!(
+				"Failed to run {}: {e}",
+				command.get_program().to_string_lossy()
+			);
+			true
+		}
+	};
+	if failed {
+		eprintln!("Press enter to return to nix-inspect");
+		let _ = io::stdin().read_line(&mut String::new());
+	}
+
+	enable_raw_mode()?;
+	stdout().execute(EnterAlternateScreen)?;
+	stdout().execute(EnableMouseCapture)?;
+	KEY_HANDLER_PAUSED.store(false, Ordering::SeqCst);
+	status
+}
@@ -1,44 +1,541 @@
-use std::path::PathBuf;
+use std::{
+	collections::HashSet,
+	io::Write,
+	path::PathBuf,
+	process::Command,
+	time::{Duration, Instant},
+};
 
-use crossterm::event::{self, Event, KeyCode};
+use crossterm::event::{self, Event, KeyCode, MouseButton, MouseEvent, MouseEventKind};
+use ratatui::layout::Rect;
 
 use crate::{
-	key_handler::handle_key,
+	batch::BatchState,
+	bookmarks::{BookmarkRow, BookmarkScope},
+	clipboard::{self, leaf_text},
+	diff::{DiffSide, DiffState},
+	discover,
+	eval::{eval_expr, is_eval_path, repl_expr, select_expr, EvalSession, EVAL_PREFIX},
+	file_preview::FilePreview,
+	key_handler::{handle_key, input_mode, PendingKeys},
+	keymap::Action,
+	load_config, load_diff_config,
 	model::{
-		next, prev, select_next, select_prev, Bookmark, BrowserPath, BrowserStackItem, InputModel,
-		InputState, Message, Model, PathData, RunningState,
+		next, prev, select_next, select_prev, Bookmark, BrowserPath, BrowserStackItem, HelpState,
+		InputModel, InputState, Message, Model, PathData, RunningState, ROOT_ENTRY, SERVICES_ENTRY,
 	},
+	services::Services,
+	sort::{sort_list, SORT_PREFETCH},
+	system_compare::{SystemCompare, COMPONENTS},
+	tree::{TreeState, TREE_PREFETCH},
+	tui,
 	view::ViewData,
-	Config,
+	workers::WorkerHost,
+	Args, Config,
 };
 
 pub struct UpdateContext {
 	pub req_tx: kanal::Sender<BrowserPath>,
+	/// Requests for the right hand side of a diff against a second root expression
+	pub diff_req_tx: Option<kanal::Sender<BrowserPath>>,
 	pub config_path: PathBuf,
+	pub args: Args,
+	/// Worker results are forwarded to the main loop through this
+	pub msg_tx: kanal::Sender<Message>,
+	/// Bumped whenever the workers are respawned, so results still coming in from the old ones
+	/// can be dropped
+	pub worker_generation: usize,
+	/// Expressions evaluated from the `:` prompt whose results are still on the visit stack
+	pub eval_sessions: Vec<EvalSession>,
+	/// Set when another program had the terminal, so the next frame has to be drawn from scratch
+	pub clear_terminal: bool,
+	/// Time and position of the last click, for detecting double clicks
+	pub last_click: Option<(Instant, u16, u16)>,
+	/// Lists sorted by the values of their children that got new values, sorted again once the
+	/// results that are already waiting have been stored
+	pub unsorted: HashSet<BrowserPath>,
+	/// Home-manager users being looked up in the background, one system at a time
+	pub discovering_users: Option<BrowserPath>,
+}
+
+/// Two clicks on the same spot within this time enter the clicked item
+const DOUBLE_CLICK: Duration = Duration::from_millis(400);
+
+/// Row of `rect` at the given position, if it is inside
+fn row_in(rect: Rect, column: u16, row: u16) -> Option<usize> {
+	let inside =
+		(rect.left()..rect.right()).contains(&column) && (rect.top()..rect.bottom()).contains(&row);
+	inside.then(|| (row - rect.top()) as usize)
+}
+
+/// Spawn the workers for the root expression and, when diffing against a second root, the right
+/// hand side, forwarding their results to the main loop
+fn spawn_workers(
+	args: &Args,
+	msg_tx: &kanal::Sender<Message>,
+	generation: usize,
+) -> color_eyre::Result<(
+	kanal::Sender<BrowserPath>,
+	Option<kanal::Sender<BrowserPath>>,
+)> {
+	let expr = load_config(args)?;
+	tracing::debug!("{}", expr);
+	let worker_host = WorkerHost::new(expr);
+	let diff_worker_host = load_diff_config(args)?.map(|expr| {
+		tracing::debug!("diffing against {}", expr);
+		WorkerHost::new(expr)
+	});
+
+	if let Some(diff_worker_host) = &diff_worker_host {
+		let worker_rx = diff_worker_host.rx.clone();
+		let tx = msg_tx.clone();
+		std::thread::spawn(move || {
+			while let Ok((p, v)) = worker_rx.recv() {
+				let _ = tx.send(Message::DiffData(generation, p, v));
+			}
+		});
+	}
+
+	{
+		let worker_rx = worker_host.rx.clone();
+		let tx = msg_tx.clone();
+		std::thread::spawn(move || {
+			while let Ok((p, v)) = worker_rx.recv() {
+				let _ = tx.send(Message::Data(generation, p, v));
+			}
+		});
+	}
+
+	Ok((worker_host.tx, diff_worker_host.map(|x| x.tx)))
 }
 
+/// Spawn the worker of an evaluation session, forwarding its results below the session's root
+fn spawn_eval_worker(
+	expr: &str,
+	root: &BrowserPath,
+	msg_tx: &kanal::Sender<Message>,
+	generation: usize,
+) -> kanal::Sender<BrowserPath> {
+	tracing::debug!("evaluating {}", expr);
+	let worker_host = WorkerHost::new(expr.to_string());
+	let worker_rx = worker_host.rx.clone();
+	let root = root.0[0].clone();
+	let tx = msg_tx.clone();
+	std::thread::spawn(move || {
+		while let Ok((mut p, v)) = worker_rx.recv() {
+			p.0[0] = root.clone();
+			let _ = tx.send(Message::Data(generation, p, v));
+		}
+	});
+	worker_host.tx
+}
+
+const NO_BOOKMARK_SELECTED: &str = "Select a bookmark in the bookmark list first";
+const NO_SYSTEM_CONFIG: &str = "No configuration of this host found in the root (yet)";
+
 pub fn save_config(path: PathBuf, config: Config) {
 	std::thread::spawn(move || {
-		let _ = std::fs::write(&path, &serde_json::to_string_pretty(&config).unwrap());
+		let _ = std::fs::write(&path, serde_json::to_string_pretty(&config).unwrap());
 	});
 }
 
 impl UpdateContext {
+	pub fn new(
+		args: Args,
+		config_path: PathBuf,
+		msg_tx: kanal::Sender<Message>,
+	) -> color_eyre::Result<UpdateContext> {
+		let (req_tx, diff_req_tx) = spawn_workers(&args, &msg_tx, 0)?;
+		Ok(UpdateContext {
+			req_tx,
+			diff_req_tx,
+			config_path,
+			args,
+			msg_tx,
+			worker_generation: 0,
+			eval_sessions: vec![],
+			clear_terminal: false,
+			last_click: None,
+			unsorted: HashSet::new(),
+			discovering_users: None,
+		})
+	}
+
+	/// Go to the level containing `path`, with the cursor on it
+	fn go_to(&self, model: &mut Model, path: BrowserPath) {
+		match path.parent() {
+			Some(parent) => {
+				model.update_parent_selection(parent.clone());
+				if let Some(list) = model.path_data.current_list_mut(&parent) {
+					let name = path.0.last().unwrap();
+					if let Some(i) = list.list.iter().position(|x| x == name) {
+						list.state.select(Some(i));
+					}
+				}
+			}
+			None => model.update_parent_selection(path),
+		}
+		for item in model.visit_stack.iter() {
+			if let BrowserStackItem::BrowserPath(p) = item {
+				self.maybe_reeval_path(p, model);
+			}
+		}
+		self.maybe_reeval_selection(model);
+	}
+
+	/// Request the first children of an expanded node of the tree view, so their values can be
+	/// shown next to them
+	fn prefetch_tree_children(&self, model: &Model, path: &BrowserPath) {
+		if let Some(list) = model.path_data.current_list(path) {
+			for name in list.list.iter().take(TREE_PREFETCH) {
+				self.maybe_reeval_path(&path.child(name.to_string()), model);
+			}
+		}
+	}
+
+	/// Sort the list at `path` by its mode, evaluating the children first if the mode needs
+	/// their values
+	fn sort(&self, model: &mut Model, path: &BrowserPath) {
+		let mode = model.sort.mode(path);
+		sort_list(&mut model.path_data, path, mode);
+		if mode.needs_values() {
+			if let Some(list) = model.path_data.current_list(path) {
+				for name in list.list.iter().take(SORT_PREFETCH) {
+					self.maybe_reeval_path(&path.child(name.to_string()), model);
+				}
+			}
+		}
+	}
+
+	/// Sort the lists whose children got new values since the last call
+	pub fn sort_unsorted(&mut self, model: &mut Model) {
+		for path in self.unsorted.drain() {
+			sort_list(&mut model.path_data, &path, model.sort.mode(&path));
+		}
+	}
+
+	/// Select the item shown at `row` of the list at `depth`, taking its scroll offset into
+	/// account
+	fn select_row(&self, model: &mut Model, depth: usize, row: usize) -> bool {
+		let selected = match model.list_state(depth) {
+			Some((state, len)) if state.offset() + row < len => {
+				state.select(Some(state.offset() + row));
+				true
+			}
+			_ => false,
+		};
+		self.maybe_reeval_selection(model);
+		selected
+	}
+
+	pub fn handle_mouse(
+		&mut self,
+		view_data: &ViewData,
+		model: &mut Model,
+		mouse: MouseEvent,
+	) -> color_eyre::Result<Option<Message>> {
+		let (column, row) = (mouse.column, mouse.row);
+		if model.help.is_some() {
+			return Ok(match mouse.kind {
+				MouseEventKind::ScrollDown => Some(Message::HelpScroll(3)),
+				MouseEventKind::ScrollUp => Some(Message::HelpScroll(-3)),
+				_ => None,
+			});
+		}
+		if model.batch.is_some() {
+			return Ok(match mouse.kind {
+				MouseEventKind::ScrollDown => Some(Message::BatchMove(3)),
+				MouseEventKind::ScrollUp => Some(Message::BatchMove(-3)),
+				_ => None,
+			});
+		}
+		if let Some(tree) = &mut model.tree {
+			return Ok(match mouse.kind {
+				MouseEventKind::ScrollDown => Some(Message::TreeMove(1)),
+				MouseEventKind::ScrollUp => Some(Message::TreeMove(-1)),
+				MouseEventKind::Down(MouseButton::Left) => {
+					let rows = tree.rows(&model.path_data);
+					let clicked = row_in(view_data.current_area, column, row)
+						.and_then(|row| rows.get(tree.list_state.offset() + row));
+					clicked.map(|x| {
+						tree.selected = x.path.clone();
+						Message::TreeMove(0)
+					})
+				}
+				_ => None,
+			});
+		}
+		match mouse.kind {
+			MouseEventKind::ScrollDown => return Ok(Some(Message::ListDown)),
+			MouseEventKind::ScrollUp => return Ok(Some(Message::ListUp)),
+			MouseEventKind::Down(MouseButton::Left) => {}
+			_ => return Ok(None),
+		}
+		let double_click = self
+			.last_click
+			.take()
+			.is_some_and(|(time, x, y)| time.elapsed() < DOUBLE_CLICK && (x, y) == (column, row));
+		if !double_click {
+			self.last_click = Some((Instant::now(), column, row));
+		}
+		let depth = model.visit_stack.len() - 1;
+
+		if let Some(clicked) = view_data.breadcrumb_at(column, row) {
+			model.visit_stack.truncate(clicked + 1);
+			self.drop_unused_eval_sessions(model);
+			self.maybe_reeval_selection(model);
+		} else if let Some(row) = row_in(view_data.current_area, column, row) {
+			if self.select_row(model, depth, row) && double_click {
+				return Ok(Some(Message::EnterItem));
+			}
+		} else if let Some(row) = row_in(view_data.previous_area, column, row) {
+			if depth > 0 {
+				model.visit_stack.pop();
+				self.drop_unused_eval_sessions(model);
+				self.select_row(model, depth - 1, row);
+			}
+		} else if let Some(row) = row_in(view_data.preview_area, column, row) {
+			let mut msg = Some(Message::EnterItem);
+			while let Some(x) = msg {
+				msg = self.update(view_data, model, x)?;
+			}
+			if model.visit_stack.len() > depth + 1 {
+				self.select_row(model, depth + 1, row);
+			}
+		}
+		Ok(None)
+	}
+
+	/// Expression for the value at `path`, and the path `lib` and `pkgs` are looked up from
+	fn value_expr(&self, model: &Model, path: &BrowserPath) -> (String, BrowserPath) {
+		match self.eval_sessions.iter().find(|x| x.contains(path)) {
+			// The result of another evaluation is selected from that evaluation's expression
+			Some(session) => (
+				select_expr(&session.expr, path, &model.path_data),
+				session.origin.clone(),
+			),
+			None => (select_expr("root", path, &model.path_data), path.clone()),
+		}
+	}
+
+	/// Evaluate `input` with `_` bound to the value under the cursor, and browse the result
+	pub fn start_eval(&mut self, model: &mut Model, input: &str) -> color_eyre::Result<()> {
+		let (value, origin) = self.value_expr(model, &model.cursor_path());
+		let expr = eval_expr(
+			&load_config(&self.args)?,
+			&value,
+			&origin,
+			&model.path_data,
+			input,
+		);
+		let root = BrowserPath(vec![format!("{EVAL_PREFIX}{input}")]);
+
+		// Evaluating the same expression again replaces the previous result
+		let depth = model.visit_stack.iter().position(
+			|x| matches!(x, BrowserStackItem::BrowserPath(p) if p.0.first() == root.0.first()),
+		);
+		if let Some(depth) = depth {
+			model.visit_stack.truncate(depth);
+		}
+		self.drop_unused_eval_sessions(model);
+
+		let tx = spawn_eval_worker(&expr, &root, &self.msg_tx, self.worker_generation);
+		self.eval_sessions.push(EvalSession {
+			root: root.clone(),
+			origin,
+			expr,
+			tx,
+		});
+		model.visit_stack.push_path(root.clone());
+		self.queue_reeval(&root);
+		Ok(())
+	}
+
+	/// Suspend the TUI and open `nix repl` with the root expression's attributes and the value
+	/// under the cursor as `_` in scope
+	pub fn open_repl(&mut self, model: &Model) -> color_eyre::Result<()> {
+		let path = model.cursor_path();
+		let (value, origin) = self.value_expr(model, &path);
+		let expr = repl_expr(&load_config(&self.args)?, &value, &origin, &model.path_data);
+		// Created with a random name that no one else can open, and removed when dropped
+		let mut file = tempfile::Builder::new()
+			.prefix("nix-inspect-repl-")
+			.suffix(".nix")
+			.tempfile()?;
+		file.write_all(expr.as_bytes())?;
+
+		let mut command = Command::new("nix");
+		command
+			.args([
+				"repl",
+				"--extra-experimental-features",
+				"nix-command flakes",
+			])
+			.arg("--file")
+			.arg(file.path());
+		let value_name = match path.to_expr() {
+			x if x.is_empty() => "root".to_string(),
+			x => x,
+		};
+		let banner = format!(
+			"_ = {value_name}, with root, lib and pkgs in scope. Exit the repl to return to nix-inspect."
+		);
+		let status = tui::run_suspended(&mut command, &banner);
+		self.clear_terminal = true;
+		status?;
+		Ok(())
+	}
+
+	/// Copy `text` to the clipboard and report it in the status line
+	fn copy(&self, model: &mut Model, description: &str, text: &str) {
+		model.status_message = Some(match clipboard::copy(text) {
+			Ok(()) => format!("Copied {description} ({} bytes)", text.len()),
+			Err(e) => format!("Failed to copy {description}: {e}"),
+		});
+	}
+
+	/// Evaluate the JSON of the value at `path` in the background and copy it once it is done
+	pub fn copy_json(&self, model: &mut Model, path: &BrowserPath) -> color_eyre::Result<()> {
+		let (value, _) = self.value_expr(model, path);
+		let expr = format!(
+			"let root = ({}); in builtins.toJSON {value}",
+			load_config(&self.args)?
+		);
+		let description = format!("JSON of {}", path.to_expr());
+		model.status_message = Some(format!("Evaluating {description} ..."));
+
+		let worker_host = WorkerHost::new(expr);
+		let tx = self.msg_tx.clone();
+		std::thread::spawn(move || {
+			let _ = worker_host.tx.send(BrowserPath::from(String::new()));
+			while let Ok((_, data)) = worker_host.rx.recv() {
+				if !matches!(data, PathData::Loading) {
+					let _ = tx.send(Message::CopyResult(description, data));
+					break;
+				}
+			}
+		});
+		Ok(())
+	}
+
+	/// Stop the workers of evaluations that are no longer on the visit stack and forget their
+	/// results
+	pub fn drop_unused_eval_sessions(&mut self, model: &mut Model) {
+		let visit_stack = &model.visit_stack;
+		self.eval_sessions.retain(|session| {
+			visit_stack
+				.iter()
+				.any(|x| matches!(x, BrowserStackItem::BrowserPath(p) if session.contains(p)))
+		});
+		let sessions = &self.eval_sessions;
+		model
+			.path_data
+			.retain(|p, _| !is_eval_path(p) || sessions.iter().any(|x| x.contains(p)));
+	}
+
+	/// Respawn the workers so the root expression is evaluated from scratch, then request
+	/// everything on the visit stack again
+	pub fn reload(&mut self, model: &mut Model) -> color_eyre::Result<()> {
+		let generation = self.worker_generation + 1;
+		let (req_tx, diff_req_tx) = spawn_workers(&self.args, &self.msg_tx, generation)?;
+		self.req_tx = req_tx;
+		self.diff_req_tx = diff_req_tx;
+		self.worker_generation = generation;
+		for session in &mut self.eval_sessions {
+			session.tx = spawn_eval_worker(&session.expr, &session.root, &self.msg_tx, generation);
+		}
+
+		let path_data = &model.path_data;
+		model.restored_selections = model
+			.visit_stack
+			.iter()
+			.filter_map(|item| match item {
+				BrowserStackItem::BrowserPath(p) => {
+					let list = path_data.current_list(p)?;
+					let selected = list.list.get(list.state.selected()?)?;
+					Some((p.clone(), selected.clone()))
+				}
+				_ => None,
+			})
+			.collect();
+		model.path_data.clear();
+		model.file_previews.clear();
+		self.discovering_users = None;
+		if let Some(compare) = &mut model.system_compare {
+			// The running system may have been switched in the meantime
+			compare.live = SystemCompare::new(compare.config.clone()).live;
+		}
+		if let Some(diff) = &mut model.diff {
+			diff.requested.clear();
+			if let Some(right_data) = &mut diff.right_data {
+				right_data.clear();
+			}
+		}
+
+		let mut paths = model
+			.visit_stack
+			.iter()
+			.filter_map(|item| match item {
+				BrowserStackItem::BrowserPath(p) => Some(p.clone()),
+				_ => None,
+			})
+			.collect::<Vec<_>>();
+		paths.extend(discover::discovery_paths(&model.path_data));
+		if let Some(compare) = &model.system_compare {
+			paths.extend(compare.out_paths());
+		}
+		// The enable options follow once the groups are in again
+		if let Some(services) = &model.services {
+			paths.extend(services.group_paths());
+		}
+		self.queue_reevals(&paths);
+		self.maybe_reeval_selection(model);
+		Ok(())
+	}
+
 	pub fn queue_reeval(&self, path: &BrowserPath) {
-		let path = path.clone();
-		let req_tx = self.req_tx.clone();
+		self.queue_reevals(std::slice::from_ref(path));
+	}
+
+	/// Evaluate `paths` again, sending the requests in order from a single thread
+	pub fn queue_reevals(&self, paths: &[BrowserPath]) {
+		let requests = paths
+			.iter()
+			.map(
+				|path| match self.eval_sessions.iter().find(|x| x.contains(path)) {
+					Some(session) => (session.tx.clone(), session.worker_path(path)),
+					None => (self.req_tx.clone(), path.clone()),
+				},
+			)
+			.collect::<Vec<_>>();
 		std::thread::spawn(move || {
-			let _ = req_tx.send(path.clone());
+			for (req_tx, path) in requests {
+				let _ = req_tx.send(path);
+			}
 		});
 	}
 
 	pub fn maybe_reeval_path(&self, path: &BrowserPath, model: &Model) {
-		if model.path_data.get(&path).is_none() {
+		if model.path_data.lookup(path).is_none() || model.path_data.needs_confirmation(path) {
 			let path = path.clone();
 			self.queue_reeval(&path);
 		}
 	}
 
+	/// Request the paths that are not loaded yet, from a single thread
+	pub fn maybe_reeval_paths(&self, paths: &[BrowserPath], model: &Model) {
+		let missing = paths
+			.iter()
+			.filter(|x| {
+				model.path_data.lookup(x).is_none() || model.path_data.needs_confirmation(x)
+			})
+			.cloned()
+			.collect::<Vec<_>>();
+		if !missing.is_empty() {
+			self.queue_reevals(&missing);
+		}
+	}
+
 	pub fn maybe_reeval_parent(&self, model: &Model) {
 		if let Some(BrowserStackItem::BrowserPath(path)) = model.visit_stack.prev_item() {
 			self.maybe_reeval_path(path, model);
@@ -46,7 +543,7 @@ impl UpdateContext {
 	}
 
 	pub fn maybe_reeval_selection_browser(&self, p: &BrowserPath, model: &Model) {
-		let current_value = match model.path_data.get(&p) {
+		let current_value = match model.path_data.get(p) {
 			Some(x) => x,
 			None => return,
 		};
@@ -54,7 +551,7 @@ impl UpdateContext {
 			PathData::List(x) => x,
 			_ => return,
 		};
-		let selected = match list.selected(&p) {
+		let selected = match list.selected(p) {
 			Some(x) => x,
 			None => return,
 		};
@@ -77,17 +574,64 @@ impl UpdateContext {
 			}
 			BrowserStackItem::Recents => {
 				if let Some(x) = model.selected_recent() {
+					self.maybe_reeval_path(x, model);
+				}
+			}
+			BrowserStackItem::Services => {
+				if let Some(x) = model
+					.services
+					.as_ref()
+					.and_then(|x| x.selected_path(&model.path_data))
+				{
 					self.maybe_reeval_path(&x, model);
 				}
 			}
-			BrowserStackItem::Root => {}
+			BrowserStackItem::Root => {
+				let entry = model.selected_root_entry();
+				if let Some(entry) = &entry {
+					self.maybe_reeval_path(&entry.path, model);
+				}
+				let root_selected = model.root_view_state.selected() == Some(ROOT_ENTRY);
+				if entry.is_some() || root_selected {
+					if let Some(users) =
+						discover::users_to_discover(&model.path_data, entry.as_ref())
+					{
+						self.maybe_reeval_path(&users, model);
+					}
+				}
+			}
+			BrowserStackItem::SystemCompare => {}
+		}
+	}
+
+	/// Request everything needed to diff the children of the current path on both sides
+	pub fn maybe_reeval_diff(&self, model: &mut Model) {
+		let current = match model.visit_stack.current() {
+			Some(x) => x.clone(),
+			None => return,
+		};
+		let diff = match &mut model.diff {
+			Some(x) => x,
+			None => return,
+		};
+		for (side, path) in diff.required_paths(&model.path_data, &current) {
+			let req_tx = match (side, &self.diff_req_tx) {
+				(DiffSide::Right, Some(diff_req_tx)) => diff_req_tx.clone(),
+				_ => self.req_tx.clone(),
+			};
+			diff.requested.insert((side, path.clone()));
+			std::thread::spawn(move || {
+				let _ = req_tx.send(path);
+			});
 		}
 	}
 
-	pub fn maybe_reeval_selection(&self, model: &Model) {
+	/// Request the selected item, and what is needed to diff the current path if diffing
+	pub fn maybe_reeval_selection(&self, model: &mut Model) {
 		if let Some(x) = model.visit_stack.last() {
 			self.maybe_reeval_current_selection(x, model);
 		}
+		self.maybe_reeval_diff(model);
 	}
 
 	pub fn update(
@@ -98,29 +642,145 @@ impl UpdateContext {
 	) -> color_eyre::Result<Option<Message>> {
 		match msg {
 			Message::TermEvent(event) => match event {
-				Event::Key(key) => {
-					if key.kind == event::KeyEventKind::Press {
-						if let Some(msg) = handle_key(key, &model) {
-							return Ok(Some(msg));
-						}
+				Event::Key(key) if key.kind == event::KeyEventKind::Press => {
+					model.status_message = None;
+					let msg = handle_key(key, model);
+					// Any key that doesn't continue a multi-key command ends it
+					if !matches!(msg, Some(Message::PendingKeys(_))) {
+						model.pending_keys = PendingKeys::default();
+					}
+					if let Some(msg) = msg {
+						return Ok(Some(msg));
 					}
 				}
+				Event::Mouse(mouse) => return self.handle_mouse(view_data, model, mouse),
 				_ => {}
 			},
-			Message::Data(p, d) => {
-				let data = d.clone();
-				model
-					.path_data
-					.entry(p)
-					.and_modify(|x| match (x, data) {
-						(PathData::List(p), PathData::List(d)) => {
-							let cursor = p.state.selected().unwrap_or(0);
-							p.state.select(Some(cursor.min(d.list.len()).max(0)));
-							p.list = d.list;
+			Message::Data(generation, p, d) => {
+				if generation != self.worker_generation {
+					return Ok(None);
+				}
+				if let Some(target) = d.file_target() {
+					if !model.file_previews.contains_key(target) {
+						let target = target.to_string();
+						model
+							.file_previews
+							.insert(target.clone(), FilePreview::loading(&target));
+						let msg_tx = self.msg_tx.clone();
+						std::thread::spawn(move || {
+							let preview = FilePreview::load(&target);
+							let _ = msg_tx.send(Message::FilePreview(target, preview));
+						});
+					}
+				}
+				if let Some(batch) = &mut model.batch {
+					batch.record(&p, &d);
+					// Whether children like services are enabled is part of the summary
+					if batch.child_name(&p).is_some() {
+						if let PathData::List(list) = &d {
+							if list.list.iter().any(|x| x == "enable") {
+								self.queue_reeval(&p.child("enable".to_string()));
+							}
 						}
-						x @ _ => *x.0 = x.1,
-					})
-					.or_insert(d.clone());
+					}
+				}
+				let restored = match d {
+					PathData::List(_) => model.restored_selections.remove(&p),
+					_ => None,
+				};
+				if self.discovering_users == Some(p.clone()) && !matches!(d, PathData::Loading) {
+					self.discovering_users = None;
+				}
+				model.path_data.insert_data(p.clone(), d);
+				if let Some(name) = restored {
+					model.restore_selection(&p, &name);
+				}
+				let discovery_paths = discover::discovery_paths(&model.path_data);
+				if discovery_paths.contains(&p) {
+					self.maybe_reeval_paths(&discovery_paths, model);
+				}
+				if self.discovering_users.is_none() {
+					self.discovering_users = discover::next_users_to_discover(&model.path_data);
+					if let Some(users) = &self.discovering_users {
+						self.queue_reeval(users);
+					}
+				}
+				if let Some(services) = &model.services {
+					let enable_paths = services.enable_paths(&p, &model.path_data);
+					self.maybe_reeval_paths(&enable_paths, model);
+				}
+				if model.path_data.current_list(&p).is_some() && !model.sort.mode(&p).is_default() {
+					self.sort(model, &p);
+				}
+				if let Some(parent) = p.parent() {
+					if model.sort.mode(&parent).needs_values() {
+						self.unsorted.insert(parent);
+					}
+				}
+				if model.tree.as_ref().is_some_and(|x| x.expanded.contains(&p)) {
+					self.prefetch_tree_children(model, &p);
+				}
+				if model.path_data.over_limit() {
+					let pinned = model.pinned_paths();
+					let evicted = model.path_data.evict(&pinned);
+					if let Some(diff) = &mut model.diff {
+						diff.forget(&evicted);
+					}
+				}
+				self.maybe_reeval_selection(model);
+			}
+			Message::DiffData(generation, p, d) => {
+				if generation != self.worker_generation {
+					return Ok(None);
+				}
+				if let Some(right_data) = model.diff.as_mut().and_then(|x| x.right_data.as_mut()) {
+					right_data.insert_data(p, d);
+				}
+				self.maybe_reeval_diff(model);
+			}
+			Message::DiffMark => {
+				if model.diff.take().is_some() {
+					return Ok(None);
+				}
+				let selected = model.visit_stack.current().and_then(|x| {
+					model
+						.path_data
+						.current_list(x)
+						.and_then(|list| list.selected(x))
+				});
+				match (model.diff_mark.take(), selected) {
+					(Some(left), Some(right)) if left != right => {
+						let diff = DiffState::new(left.clone(), right, false);
+						model.update_parent_selection(left);
+						model.diff = Some(diff);
+						self.maybe_reeval_selection(model);
+					}
+					(_, selected) => model.diff_mark = selected,
+				}
+			}
+			Message::DiffNext | Message::DiffPrev => {
+				let (diff, current) = match (&model.diff, model.visit_stack.current()) {
+					(Some(diff), Some(current)) => (diff, current),
+					_ => return Ok(None),
+				};
+				let statuses = match diff.list_diff(&model.path_data, current) {
+					Some(x) => x.statuses,
+					None => return Ok(None),
+				};
+				let current = current.clone();
+				if let Some(list) = model.path_data.current_list_mut(&current) {
+					let cursor = list.state.selected().unwrap_or(0);
+					let len = statuses.len();
+					let found = (1..=len)
+						.map(|offset| match msg {
+							Message::DiffNext => (cursor + offset) % len,
+							_ => (cursor + len - offset) % len,
+						})
+						.find(|&i| statuses[i].is_difference());
+					if let Some(i) = found {
+						list.state.select(Some(i));
+					}
+				}
 				self.maybe_reeval_selection(model);
 			}
 			Message::CurrentPath(p) => {
@@ -128,6 +788,8 @@ impl UpdateContext {
 				self.maybe_reeval_selection(model);
 			}
 			Message::Refresh => {
+				// Store paths may have been built since they were first previewed
+				model.file_previews.clear();
 				if let Some(path) = model.visit_stack.current() {
 					self.queue_reeval(path);
 					if let Some(data) = model.path_data.current_list(path) {
@@ -144,29 +806,41 @@ impl UpdateContext {
 				}
 			}
 			Message::PageUp => {
+				if model.scroll_string_view(-(view_data.current_list_height.max(1) as isize / 2)) {
+					return Ok(None);
+				}
 				if let Some(x) = model.visit_stack.current() {
 					if let Some(list) = model.path_data.current_list_mut(x) {
 						let cursor = list.state.selected().unwrap_or(0);
-						list.state.select(Some(
-							cursor.saturating_sub(view_data.current_list_height.max(1) as usize / 2)
-								as usize,
-						));
+						list.state
+							.select(Some(cursor.saturating_sub(
+								view_data.current_list_height.max(1) as usize / 2,
+							)));
 					}
 				}
 			}
 			Message::PageDown => {
+				if model.scroll_string_view(view_data.current_list_height.max(1) as isize / 2) {
+					return Ok(None);
+				}
 				if let Some(x) = model.visit_stack.current() {
 					if let Some(list) = model.path_data.current_list_mut(x) {
 						let cursor = list.state.selected().unwrap_or(0);
 						*list.state.selected_mut() = Some(
 							(cursor + (view_data.current_list_height.max(1) / 2) as usize)
-								.max(0)
 								.min(list.list.len() - 1),
 						);
 					}
 				}
 			}
 			Message::SearchNext | Message::SearchPrev => {
+				if let InputState::Active(ref input_model) = model.search_input {
+					let needle = input_model.input.clone();
+					let offset = if let Message::SearchNext = msg { 1 } else { -1 };
+					if model.search_string_view(&needle, offset) {
+						return Ok(None);
+					}
+				}
 				if let InputState::Active(ref mut input_model) = model.search_input {
 					let current_list = match model.visit_stack.current().cloned() {
 						Some(x) => match model.path_data.current_list_mut(&x) {
@@ -204,9 +878,14 @@ impl UpdateContext {
 			}
 			Message::SearchExit => model.search_input = InputState::default(),
 			Message::SearchInput(ev) => {
+				let viewing_string = model.current_string().is_some();
 				if let InputState::Active(ref mut input_model) = model.search_input {
 					input_model.handle_key_event(ev);
 					match ev.code {
+						KeyCode::Char(_) if viewing_string => {
+							let needle = input_model.input.clone();
+							model.search_string_view(&needle, 0);
+						}
 						KeyCode::Char(_) => {
 							let current_list = match model.visit_stack.current().cloned() {
 								Some(x) => match model.path_data.current_list_mut(&x) {
@@ -230,6 +909,7 @@ impl UpdateContext {
 				}
 			}
 			Message::BookmarkInputEnter => {
+				model.bookmark_edit = None;
 				let path_str = model
 					.visit_stack
 					.current()
@@ -242,8 +922,56 @@ impl UpdateContext {
 					input: path_str.to_string(),
 				})
 			}
+			Message::EvalInputEnter => {
+				model.eval_input = InputState::Active(InputModel {
+					typing: true,
+					cursor_position: 0,
+					input: String::new(),
+				})
+			}
+			Message::EvalInputExit => model.eval_input = InputState::Normal,
+			Message::EvalInput(key) => {
+				if let InputState::Active(ref mut x) = model.eval_input {
+					x.handle_key_event(key);
+				}
+			}
+			Message::Eval => {
+				let input = match std::mem::take(&mut model.eval_input) {
+					InputState::Active(x) => x.input,
+					InputState::Normal => return Ok(None),
+				};
+				if !input.trim().is_empty() {
+					self.start_eval(model, input.trim())?;
+				}
+			}
+			Message::Repl => self.open_repl(model)?,
+			Message::CopyPath => {
+				let path = model.cursor_path().to_expr();
+				self.copy(model, "path", &path);
+			}
+			Message::CopyValue => {
+				let path = model.cursor_path();
+				match model.path_data.get(&path) {
+					Some(PathData::List(_)) => self.copy_json(model, &path)?,
+					Some(data) => match leaf_text(data) {
+						Some(text) => self.copy(model, "value", &text),
+						None => {
+							model.status_message =
+								Some(format!("Nothing to copy for a {}", data.get_type()))
+						}
+					},
+					None => model.status_message = Some("Value is not evaluated yet".to_string()),
+				}
+			}
+			Message::CopyResult(description, data) => match data {
+				PathData::String(json) => self.copy(model, &description, &json),
+				data => {
+					model.status_message = Some(format!("Failed to evaluate {description}: {data}"))
+				}
+			},
 			Message::BookmarkInputExit => {
 				model.new_bookmark_input = InputState::Normal;
+				model.bookmark_edit = None;
 			}
 			Message::BookmarkInput(key) => {
 				if let InputState::Active(ref mut x) = model.new_bookmark_input {
@@ -282,10 +1010,9 @@ impl UpdateContext {
 			}
 			Message::NavigatorEnter => {
 				let current_path = model.visit_stack.current();
-				let path_str = ".".to_string()
-					+ &current_path
-						.map(|x| x.to_expr() + if x.0.len() > 1 { "." } else { "" })
-						.unwrap_or("nixosConfigurations.".to_string());
+				let path_str = current_path
+					.map(|x| x.to_string() + ".")
+					.unwrap_or(".nixosConfigurations.".to_string());
 				model.path_navigator_input = InputState::Active(InputModel {
 					typing: true,
 					cursor_position: path_str.len(),
@@ -383,10 +1110,11 @@ impl UpdateContext {
 										parent_list.state.select(Some(nearest_occurrence_index));
 										let nearest_occurrence =
 											&parent_list.list[nearest_occurrence_index];
-										let new_path =
-											parent.child(nearest_occurrence.to_string()).to_expr();
-										x.cursor_position = new_path.len() + 1;
-										x.input = ".".to_string() + &new_path;
+										let new_path = parent
+											.child(nearest_occurrence.to_string())
+											.to_string();
+										x.cursor_position = new_path.len();
+										x.input = new_path;
 									}
 								}
 							}
@@ -399,7 +1127,20 @@ impl UpdateContext {
 				}
 			}
 			Message::CreateBookmark => {
-				if let Some(p) = model.visit_stack.current() {
+				if let Some((x, field)) = model.bookmark_edit.take() {
+					let input = match std::mem::take(&mut model.new_bookmark_input) {
+						InputState::Active(state) => state.input,
+						InputState::Normal => return Ok(None),
+					};
+					if let Some(bookmark) = model.config.bookmarks.get_mut(x, &model.root_id) {
+						field.set(bookmark, &input);
+						let path = bookmark.path.clone();
+						// A new group moves the bookmark to another heading
+						model.select_bookmark(x);
+						self.maybe_reeval_path(&path, model);
+						save_config(self.config_path.clone(), model.config.clone());
+					}
+				} else if let Some(p) = model.visit_stack.current() {
 					if let InputState::Active(state) = &model.new_bookmark_input {
 						let name = &state.input;
 						let target_path = model
@@ -408,13 +1149,20 @@ impl UpdateContext {
 							.and_then(|x| model.path_data.current_list(x))
 							.and_then(|x| x.selected(p));
 
-						model.config.bookmarks.push(Bookmark {
-							display: if name.len() > 0 {
+						let root_id = &model.root_id;
+						let bookmarks = model
+							.config
+							.bookmarks
+							.scope_mut(BookmarkScope::Root, root_id);
+						bookmarks.push(Bookmark {
+							name: if !name.is_empty() {
 								name.to_string()
 							} else {
 								p.0.last().unwrap_or(&"".to_string()).clone()
 							},
 							path: target_path.unwrap_or(p.clone()),
+							group: None,
+							note: None,
 						});
 						model.new_bookmark_input = InputState::Normal;
 						save_config(self.config_path.clone(), model.config.clone());
@@ -422,18 +1170,56 @@ impl UpdateContext {
 				}
 			}
 			Message::DeleteBookmark => {
-				if let Some(i) = model.bookmark_view_state.selected() {
-					model.config.bookmarks.remove(i);
-					let bookmarks_len = model.config.bookmarks.len();
+				if let Some(x) = model.selected_bookmark_ref() {
+					model.config.bookmarks.remove(x, &model.root_id);
+					let bookmarks_len = model.bookmark_rows().len();
 					let selected = model.bookmark_view_state.selected_mut();
-					let new = selected.map(|x| x.min(bookmarks_len - 1));
+					let new = selected.map(|x| x.min(bookmarks_len.saturating_sub(1)));
 					*selected = new;
 				}
 				save_config(self.config_path.clone(), model.config.clone());
 			}
+			Message::ToggleBookmarkScope => {
+				let Some(x) = model.editable_bookmark() else {
+					model.status_message = Some(NO_BOOKMARK_SELECTED.to_string());
+					return Ok(None);
+				};
+				if let Some(moved) = model.config.bookmarks.toggle_scope(x, &model.root_id) {
+					model.select_bookmark(moved);
+					model.status_message =
+						Some(format!("Moved the bookmark to {}", moved.scope.name()));
+					save_config(self.config_path.clone(), model.config.clone());
+				}
+			}
+			Message::EditBookmark(field) => {
+				let Some(x) = model.editable_bookmark() else {
+					model.status_message = Some(NO_BOOKMARK_SELECTED.to_string());
+					return Ok(None);
+				};
+				if let Some(bookmark) = model.config.bookmarks.get(x, &model.root_id) {
+					let input = field.get(bookmark);
+					model.bookmark_edit = Some((x, field));
+					model.new_bookmark_input = InputState::Active(InputModel {
+						typing: true,
+						cursor_position: input.len(),
+						input,
+					});
+				}
+			}
+			Message::MoveBookmark(delta) => {
+				let Some(x) = model.editable_bookmark() else {
+					model.status_message = Some(NO_BOOKMARK_SELECTED.to_string());
+					return Ok(None);
+				};
+				if let Some(moved) = model.config.bookmarks.move_by(x, delta, &model.root_id) {
+					model.select_bookmark(moved);
+					save_config(self.config_path.clone(), model.config.clone());
+				}
+			}
 			Message::Back => {
 				if model.visit_stack.len() > 1 {
 					model.visit_stack.pop();
+					self.drop_unused_eval_sessions(model);
 					self.maybe_reeval_selection(model);
 				}
 			}
@@ -452,18 +1238,33 @@ impl UpdateContext {
 							model.visit_stack.push(BrowserStackItem::Recents);
 							self.maybe_reeval_current_selection(&BrowserStackItem::Recents, model);
 						}
-						Some(2) => {
+						Some(SERVICES_ENTRY) => {
+							let Some(config) = model.system_config_root() else {
+								model.status_message = Some(NO_SYSTEM_CONFIG.to_string());
+								return Ok(None);
+							};
+							let services = Services::new(config);
+							self.maybe_reeval_paths(&services.group_paths(), model);
+							model.services = Some(services);
+							model.visit_stack.push(BrowserStackItem::Services);
+						}
+						Some(ROOT_ENTRY) => {
 							let x = BrowserPath::from("".to_string());
 							self.maybe_reeval_selection_browser(&x, model);
 							model.visit_stack.push_path(x);
 						}
-						_ => unreachable!(),
+						_ => {
+							if let Some(entry) = model.selected_root_entry() {
+								self.maybe_reeval_selection_browser(&entry.path, model);
+								model.visit_stack.push_path(entry.path);
+							}
+						}
 					};
 				}
 				BrowserStackItem::BrowserPath(p) => {
 					if let Some(selected_item) = model
 						.path_data
-						.current_list(&p)
+						.current_list(p)
 						.and_then(|list| list.state.selected().and_then(|i| list.list.get(i)))
 					{
 						let x = p.child(selected_item.clone());
@@ -471,74 +1272,369 @@ impl UpdateContext {
 						model.visit_stack.push_path(x);
 					}
 				}
-				BrowserStackItem::Bookmarks => {
-					if let Some(x) = model.selected_bookmark() {
-						self.maybe_reeval_selection_browser(&x.path, model);
-						model.visit_stack.push_path(x.path.clone());
+				BrowserStackItem::Bookmarks => match model.selected_bookmark_row() {
+					Some(BookmarkRow::Group { name, .. }) => {
+						model.config.bookmarks.toggle_collapsed(&name);
+						save_config(self.config_path.clone(), model.config.clone());
 					}
-				}
+					Some(BookmarkRow::Bookmark(_)) => {
+						if let Some(x) = model.selected_bookmark() {
+							self.maybe_reeval_selection_browser(&x.path, model);
+							model.visit_stack.push_path(x.path.clone());
+						}
+					}
+					None => {}
+				},
 				BrowserStackItem::Recents => {
 					if let Some(x) = model.selected_recent() {
-						self.maybe_reeval_selection_browser(&x, model);
+						self.maybe_reeval_selection_browser(x, model);
 						model.visit_stack.push_path(x.clone());
 					}
 				}
+				BrowserStackItem::SystemCompare => {
+					if let Some(x) = model
+						.system_compare
+						.as_ref()
+						.and_then(|x| x.selected_path())
+					{
+						self.maybe_reeval_path(&x, model);
+						model.update_parent_selection(x);
+						self.maybe_reeval_parent(model);
+					}
+				}
+				BrowserStackItem::Services => {
+					if let Some(x) = model
+						.services
+						.as_ref()
+						.and_then(|x| x.selected_path(&model.path_data))
+					{
+						self.maybe_reeval_path(&x, model);
+						model.update_parent_selection(x);
+						self.maybe_reeval_parent(model);
+						self.maybe_reeval_selection(model);
+					}
+				}
 			},
+			Message::SystemCompareEnter => {
+				let Some(config) = model.system_config_root() else {
+					model.status_message = Some(NO_SYSTEM_CONFIG.to_string());
+					return Ok(None);
+				};
+				let compare = SystemCompare::new(config);
+				for path in compare.out_paths() {
+					self.maybe_reeval_path(&path, model);
+				}
+				model.system_compare = Some(compare);
+				model.visit_stack.push(BrowserStackItem::SystemCompare);
+			}
 			Message::ListUp => {
+				if model.scroll_string_view(-1) {
+					return Ok(None);
+				}
 				let x = model.visit_stack.last().unwrap_or(&BrowserStackItem::Root);
 				match x {
 					BrowserStackItem::Root => {
-						select_prev(&mut model.root_view_state, 3);
+						let len = model.root_menu_len();
+						select_prev(&mut model.root_view_state, len);
 					}
 					BrowserStackItem::BrowserPath(p) => {
-						if let Some(list) = model.path_data.current_list_mut(&p) {
+						if let Some(list) = model.path_data.current_list_mut(p) {
 							let cursor = list.state.selected().unwrap_or(0);
 							list.state.select(Some(prev(cursor, list.list.len())));
 						}
 					}
 					BrowserStackItem::Bookmarks => {
-						select_prev(&mut model.bookmark_view_state, model.config.bookmarks.len());
+						let len = model.bookmark_rows().len();
+						select_prev(&mut model.bookmark_view_state, len);
 					}
 					BrowserStackItem::Recents => {
 						select_prev(&mut model.recents_view_state, model.recents.len());
 					}
+					BrowserStackItem::SystemCompare => {
+						if let Some(compare) = &mut model.system_compare {
+							select_prev(&mut compare.state, COMPONENTS.len());
+						}
+					}
+					BrowserStackItem::Services => {
+						if let Some(services) = &mut model.services {
+							let len = services.entries(&model.path_data).len();
+							if len > 0 {
+								select_prev(&mut services.state, len);
+							}
+						}
+					}
 				}
-				self.maybe_reeval_current_selection(&x, model);
+				self.maybe_reeval_current_selection(x, model);
 			}
 			Message::ListDown => {
-				let x = model.visit_stack.last().unwrap_or(&BrowserStackItem::Root);
-				match x {
+				if model.scroll_string_view(1) {
+					return Ok(None);
+				}
+				let x = model
+					.visit_stack
+					.last()
+					.cloned()
+					.unwrap_or(BrowserStackItem::Root);
+				match &x {
 					BrowserStackItem::Root => {
-						select_next(&mut model.root_view_state, 3);
-						if let Some(1) = model.root_view_state.selected() {
+						let len = model.root_menu_len();
+						select_next(&mut model.root_view_state, len);
+						// Evaluate the root before it is reached, for its preview
+						if model.root_view_state.selected() == Some(ROOT_ENTRY - 1) {
 							let req_tx = self.req_tx.clone();
 							std::thread::spawn(move || {
 								let _ = req_tx.send(BrowserPath::from("".to_string()));
 							});
 						}
+						self.maybe_reeval_selection(model);
 					}
 					BrowserStackItem::BrowserPath(p) => {
-						if let Some(list) = model.path_data.current_list_mut(&p) {
+						if let Some(list) = model.path_data.current_list_mut(p) {
 							let cursor = list.state.selected().unwrap_or(0);
 							list.state.select(Some(next(cursor, list.list.len())));
-							let selected = list.selected(&p);
-							if let Some(selected) = selected {
-								if model.path_data.get(&selected).is_none() {
-									let _ = self.req_tx.send(selected);
-								}
-							}
 						}
 						self.maybe_reeval_selection(model);
 					}
 					BrowserStackItem::Bookmarks => {
-						select_next(&mut model.bookmark_view_state, model.config.bookmarks.len());
+						let len = model.bookmark_rows().len();
+						select_next(&mut model.bookmark_view_state, len);
 					}
 					BrowserStackItem::Recents => {
 						select_next(&mut model.recents_view_state, model.recents.len());
 					}
+					BrowserStackItem::SystemCompare => {
+						if let Some(compare) = &mut model.system_compare {
+							select_next(&mut compare.state, COMPONENTS.len());
+						}
+					}
+					BrowserStackItem::Services => {
+						if let Some(services) = &mut model.services {
+							let len = services.entries(&model.path_data).len();
+							if len > 0 {
+								select_next(&mut services.state, len);
+							}
+						}
+					}
 				}
 				self.maybe_reeval_current_selection(&x, model);
 			}
+			Message::PendingKeys(pending) => model.pending_keys = pending,
+			// Moving the cursor by a count only evaluates the item it ends up on
+			Message::Repeat(count, action @ (Action::ListDown | Action::ListUp)) => {
+				let count = count.min(isize::MAX as usize) as isize;
+				let offset = match action {
+					Action::ListDown => count,
+					_ => -count,
+				};
+				if model.scroll_string_view(offset) {
+					return Ok(None);
+				}
+				if let Some((state, len)) = model.current_list_state() {
+					if len > 0 {
+						let cursor = state.selected().unwrap_or(0) as isize;
+						state.select(Some((cursor + offset).rem_euclid(len as isize) as usize));
+					}
+				}
+				self.maybe_reeval_selection(model);
+			}
+			Message::Repeat(count, action) => {
+				for _ in 0..count {
+					let mut msg = Some(action.message());
+					while let Some(x) = msg {
+						msg = self.update(view_data, model, x)?;
+					}
+				}
+			}
+			Message::ListGoto(i) => {
+				if model.scroll_string_view(isize::MIN) {
+					model.scroll_string_view(i.min(isize::MAX as usize) as isize);
+					return Ok(None);
+				}
+				if let Some((state, len)) = model.current_list_state() {
+					if len > 0 {
+						state.select(Some(i.min(len - 1)));
+					}
+				}
+				self.maybe_reeval_selection(model);
+			}
+			Message::CenterCursor => {
+				let height = view_data.current_list_height as usize;
+				if let Some((state, _)) = model.current_list_state() {
+					if let Some(selected) = state.selected() {
+						*state.offset_mut() = selected.saturating_sub(height / 2);
+					}
+				}
+			}
+			Message::SetMark(c) => {
+				let path = model.cursor_path();
+				model.status_message = Some(format!("Mark {c} set at {}", path.to_expr()));
+				model.marks.insert(c, path);
+			}
+			Message::JumpMark(c) => match model.marks.get(&c) {
+				Some(path) => self.go_to(model, path.clone()),
+				None => model.status_message = Some(format!("Mark {c} is not set")),
+			},
+			Message::FilePreview(target, preview) => {
+				model.file_previews.insert(target, preview);
+			}
+			Message::Reload => self.reload(model)?,
+			Message::Zoom => model.zoom = model.zoom.next(),
+			Message::CacheStats => model.show_cache_stats = !model.show_cache_stats,
+			Message::Sort => {
+				if let Some(path) = model.visit_stack.current().cloned() {
+					let mode = model.sort.mode(&path).next();
+					model.sort.per_path.insert(path.clone(), mode);
+					if mode.is_default() {
+						// Only the worker knows the original order
+						self.queue_reeval(&path);
+					} else {
+						self.sort(model, &path);
+					}
+					model.status_message = Some(format!("Sorted by {}", mode.name()));
+				}
+			}
+			Message::SortAll => {
+				let mode = model.sort.global.next();
+				model.sort.global = mode;
+				model.sort.per_path.clear();
+				let stack = model
+					.visit_stack
+					.iter()
+					.filter_map(|x| match x {
+						BrowserStackItem::BrowserPath(p) => Some(p.clone()),
+						_ => None,
+					})
+					.collect::<Vec<_>>();
+				if mode.is_default() {
+					// Lists elsewhere are requested in their original order when they are
+					// visited again. The ones the root menu and the services dashboard are
+					// built from are kept and requested right away, like the visit stack.
+					let mut kept = stack.clone();
+					kept.extend(discover::discovered_paths(&model.path_data));
+					if let Some(services) = &model.services {
+						kept.extend(services.group_paths());
+					}
+					model
+						.path_data
+						.retain(|p, x| !matches!(x, PathData::List(_)) || kept.contains(p));
+					self.queue_reevals(&kept);
+				} else {
+					let lists = model
+						.path_data
+						.iter()
+						.filter(|(_, x)| matches!(x, PathData::List(_)))
+						.map(|(p, _)| p.clone())
+						.collect::<Vec<_>>();
+					for path in lists {
+						sort_list(&mut model.path_data, &path, mode);
+					}
+					for path in &stack {
+						self.sort(model, path);
+					}
+				}
+				model.status_message = Some(format!("Sorted all lists by {}", mode.name()));
+			}
+			Message::Tree => match model.tree.take() {
+				Some(tree) => self.go_to(model, tree.selected),
+				None => {
+					let tree = TreeState::new(model.cursor_path());
+					for path in tree.expanded.iter() {
+						self.maybe_reeval_path(path, model);
+					}
+					self.maybe_reeval_path(&tree.selected, model);
+					model.tree = Some(tree);
+				}
+			},
+			Message::TreeMove(delta) => {
+				if let Some(tree) = &mut model.tree {
+					tree.move_by(&model.path_data, delta);
+					let selected = tree.selected.clone();
+					self.maybe_reeval_path(&selected, model);
+				}
+			}
+			Message::TreeExpand => {
+				let Some(tree) = &mut model.tree else {
+					return Ok(None);
+				};
+				let path = tree.selected.clone();
+				match model.path_data.get(&path) {
+					Some(PathData::List(list)) => {
+						if !tree.expanded.insert(path.clone()) {
+							if let Some(first) = list.list.first() {
+								tree.selected = path.child(first.to_string());
+							}
+						}
+					}
+					// Expanded once it turns out to be an attribute set or list
+					None | Some(PathData::Loading) => {
+						tree.expanded.insert(path.clone());
+					}
+					Some(_) => {}
+				}
+				let selected = tree.selected.clone();
+				self.maybe_reeval_path(&path, model);
+				self.maybe_reeval_path(&selected, model);
+				self.prefetch_tree_children(model, &path);
+			}
+			Message::TreeCollapse => {
+				if let Some(tree) = &mut model.tree {
+					if !tree.expanded.remove(&tree.selected)
+						&& tree.selected.0.len() > tree.root.0.len()
+					{
+						tree.selected = tree.selected.parent().unwrap();
+					}
+				}
+			}
+			Message::BatchEval => {
+				let Some(path) = model.visit_stack.current().cloned() else {
+					return Ok(None);
+				};
+				let Some(list) = model.path_data.current_list(&path) else {
+					return Ok(None);
+				};
+				let children = list.list.clone();
+				// Values that are already known are evaluated again to time them
+				let paths = children
+					.iter()
+					.map(|name| path.child(name.clone()))
+					.collect::<Vec<_>>();
+				self.queue_reevals(&paths);
+				model.batch = Some(BatchState::new(path, children));
+			}
+			Message::BatchMove(delta) => {
+				if let Some(batch) = &mut model.batch {
+					batch.move_by(delta, &model.path_data);
+				}
+			}
+			Message::BatchEnter => {
+				if let Some(batch) = model.batch.take() {
+					if let Some(name) = batch.selected {
+						self.go_to(model, batch.path.child(name));
+					}
+				}
+			}
+			Message::BatchExit => model.batch = None,
+			Message::Help => {
+				model.help = match model.help {
+					Some(_) => None,
+					None => Some(HelpState {
+						mode: input_mode(model).0,
+						scroll: 0,
+						half_page: 1,
+					}),
+				}
+			}
+			Message::HelpScroll(lines) => {
+				if let Some(help) = &mut model.help {
+					help.scroll = help.scroll.saturating_add_signed(lines);
+				}
+			}
+			Message::HelpPage(pages) => {
+				if let Some(help) = &mut model.help {
+					let lines = pages * help.half_page as isize;
+					help.scroll = help.scroll.saturating_add_signed(lines);
+				}
+			}
 			Message::Quit => model.running_state = RunningState::Stopped,
 		};
 		Ok(None)
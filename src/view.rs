@@ -1,26 +1,60 @@
 use ansi_to_tui::IntoText;
-use lazy_static::lazy_static;
-use ratatui::layout::Flex;
 use ratatui::text::Text;
 use ratatui::widgets::{Clear, Widget, Wrap};
 use ratatui::Frame;
 
 use ratatui::{
 	layout::{Alignment, Constraint, Direction, Layout, Rect},
-	style::{Color, Style, Stylize},
+	style::Style,
 	symbols,
 	text::{Line, Span},
 	widgets::{Block, Borders, List, ListItem, Paragraph},
 };
 
-use crate::model::{
-	BrowserPath, BrowserStackItem, InputState, ListData, Model, PathData, PathDataMap,
+use crate::{
+	batch::render_batch,
+	bookmarks::{BookmarkRow, BookmarkScope},
+	cache::render_cache_stats,
+	diff::{DiffState, DiffStatus, ListDiff},
+	discover::CONFIGURATIONS,
+	file_preview::{render_file_preview, FilePreviews},
+	key_handler::{fixed_keys, input_mode},
+	keymap::{action_name, Action, InputMode},
+	layout::{columns, Columns},
+	model::{
+		BrowserPath, BrowserStackItem, InputState, ListData, Model, PathData, PathDataMap,
+		ROOT_ENTRIES, ROOT_ENTRY, SERVICES_ENTRY,
+	},
+	services::{render_services, render_services_summary},
+	string_view::{line_count, render_string_view, Syntax},
+	system_compare::{render_system_compare, render_system_compare_preview},
+	theme::theme,
+	tree::render_tree,
 };
 
-/// View data that should be provided to the update handler (for page-up / page-down behavior)
+/// View data that should be provided to the update handler (for page-up / page-down behavior
+/// and mouse clicks)
 #[derive(Default)]
 pub struct ViewData {
 	pub current_list_height: u16,
+	pub previous_area: Rect,
+	pub current_area: Rect,
+	pub preview_area: Rect,
+	/// Columns of each visit stack item in the path bar: start, end and depth
+	pub breadcrumbs: Vec<(u16, u16, usize)>,
+}
+
+impl ViewData {
+	/// Depth of the visit stack item whose name is at this position of the path bar
+	pub fn breadcrumb_at(&self, column: u16, row: u16) -> Option<usize> {
+		if row != 0 {
+			return None;
+		}
+		self.breadcrumbs
+			.iter()
+			.find(|(start, end, _)| (*start..*end).contains(&column))
+			.map(|(_, _, depth)| *depth)
+	}
 }
 
 pub fn view(model: &mut Model, f: &mut Frame) -> ViewData {
@@ -28,25 +62,47 @@ pub fn view(model: &mut Model, f: &mut Frame) -> ViewData {
 		.direction(Direction::Vertical)
 		.constraints(vec![Constraint::Length(1), Constraint::Fill(1)])
 		.split(f.size());
-	let miller_layout = Layout::default()
-		.direction(Direction::Horizontal)
-		.constraints(vec![
-			Constraint::Percentage(20),
-			Constraint::Fill(1),
-			Constraint::Percentage(30),
-		])
-		.split(path_rect[1]);
+	if let Some(tree) = &mut model.tree {
+		let path = format!("Tree: {}", tree.selected.to_expr());
+		f.render_widget(
+			Paragraph::new(clip_left(&path, path_rect[0].width as usize - 1)),
+			Rect::new(
+				path_rect[0].x + 1,
+				path_rect[0].y,
+				path_rect[0].width - 1,
+				1,
+			),
+		);
+		let inner = render_tree(f, tree, &model.path_data, path_rect[1]);
+		let view_data = ViewData {
+			current_list_height: inner.height,
+			current_area: inner,
+			..Default::default()
+		};
+		render_bottom_and_overlays(f, model);
+		return view_data;
+	}
+	let columns = columns(path_rect[1], &model.config.layout, model.zoom);
 
 	let previous_list_block =
 		Block::default().borders(Borders::TOP | Borders::BOTTOM | Borders::LEFT);
-	let previous_inner = previous_list_block.inner(miller_layout[0]);
-	f.render_widget(previous_list_block, miller_layout[0]);
-
-	render_previous_stack(model, f, previous_inner);
+	let previous_inner = previous_list_block.inner(columns.parent);
+	if !columns.parent.is_empty() {
+		f.render_widget(previous_list_block.clone(), columns.parent);
+		render_previous_stack(model, f, previous_inner);
+	}
+	// The preview frame has no left border of its own
+	if let Some(edge) = columns.preview_edge {
+		f.render_widget(previous_list_block, edge);
+	}
 
-	let mut view_data = ViewData::default();
+	let mut view_data = ViewData {
+		previous_area: previous_inner,
+		preview_area: preview_frame().inner(columns.preview),
+		..Default::default()
+	};
 
-	let path = model
+	let names = model
 		.visit_stack
 		.iter()
 		.map(|x| match x {
@@ -54,17 +110,52 @@ pub fn view(model: &mut Model, f: &mut Frame) -> ViewData {
 			BrowserStackItem::Root => "Root".to_string(),
 			BrowserStackItem::Recents => "Recents".to_string(),
 			BrowserStackItem::Bookmarks => "Bookmarks".to_string(),
+			BrowserStackItem::SystemCompare => "System".to_string(),
+			BrowserStackItem::Services => "Services".to_string(),
 		})
-		.collect::<Vec<_>>()
-		.join(" > ");
+		.collect::<Vec<_>>();
+	let path = names.join(" > ");
 
 	let path_rect = path_rect[0];
+	// Screen columns, as the names can have characters of more than one byte
+	let skip = path
+		.chars()
+		.count()
+		.saturating_sub(path_rect.width as usize);
 
 	f.render_widget(
-		Paragraph::new(&path[path.len().saturating_sub(path_rect.width as usize)..])
-			.alignment(Alignment::Left),
+		Paragraph::new(clip_left(&path, path_rect.width as usize)).alignment(Alignment::Left),
 		Rect::new(path_rect.x + 1, path_rect.y, path_rect.width - 1, 1),
 	);
+	let mut start = 0;
+	for (depth, name) in names.iter().enumerate() {
+		let end = start + name.chars().count();
+		if end > skip {
+			let x = |i: usize| path_rect.x + 1 + (i.max(skip) - skip) as u16;
+			view_data.breadcrumbs.push((x(start), x(end), depth));
+		}
+		start = end + " > ".len();
+	}
+	// Keys typed so far of a multi-key command, where vim shows them too, after a marker for
+	// values from the disk cache that are being evaluated again
+	let stale = model
+		.visit_stack
+		.current()
+		.is_some_and(|x| model.path_data.is_stale(x))
+		|| model.path_data.is_stale(&model.cursor_path());
+	let mut status = vec![];
+	if stale {
+		status.push(Span::styled("stale ", theme().warning));
+	}
+	if !model.pending_keys.is_empty() {
+		status.push(Span::styled(model.pending_keys.to_string(), theme().key));
+	}
+	if !status.is_empty() {
+		f.render_widget(
+			Paragraph::new(Line::from(status)).alignment(Alignment::Right),
+			path_rect,
+		);
+	}
 
 	match model
 		.visit_stack
@@ -72,50 +163,82 @@ pub fn view(model: &mut Model, f: &mut Frame) -> ViewData {
 		.cloned()
 		.unwrap_or(BrowserStackItem::Root)
 	{
-		BrowserStackItem::BrowserPath(p) => match model.path_data.get_mut(&p) {
-			Some(data) if !matches!(data, PathData::List(_)) => {
-				let block = Block::new()
-					.borders(Borders::ALL)
-					.border_set(symbols::border::Set {
-						top_left: symbols::line::NORMAL.horizontal_down,
-						bottom_left: symbols::line::NORMAL.horizontal_up,
-						..symbols::border::PLAIN
-					})
-					.title_style(Style::new().blue())
-					.title(data.get_type());
-				let outer = miller_layout[2].union(miller_layout[1]);
-				let inner = block.inner(outer);
-				view_data.current_list_height = inner.height;
-				f.render_widget(block, outer);
-				let _ = render_value_preview(f, data, inner);
-			}
-			x @ _ => {
-				let current_list_block = current_frame();
-				let inner = current_list_block.inner(miller_layout[1]);
-				view_data.current_list_height = inner.height;
-				f.render_widget(current_list_block, miller_layout[1]);
-				if let Some(PathData::List(current_path_data)) = x {
-					let _ = render_list(
-						f,
-						current_path_data,
-						inner,
-						Some(&model.search_input),
-						Some(&model.path_navigator_input),
-						&model.prev_tab_completion,
-					);
+		BrowserStackItem::BrowserPath(p) => {
+			let diff = model
+				.diff
+				.as_ref()
+				.and_then(|diff| diff.list_diff(&model.path_data, &p));
+			match model.path_data.get_mut(&p) {
+				Some(data) if !matches!(data, PathData::List(_)) => {
+					let block = Block::new()
+						.borders(Borders::ALL)
+						.border_set(frame_border_set(!columns.parent.is_empty(), false))
+						.title_style(theme().title);
+					let outer = columns.preview.union(columns.current);
+					let inner = block.inner(outer);
+					view_data.current_list_height = inner.height;
+					// The value takes up the preview column as well
+					view_data.current_area = inner;
+					view_data.preview_area = Rect::default();
+					let search = match &model.search_input {
+						InputState::Active(search_model) => Some(search_model.input.as_str()),
+						InputState::Normal => None,
+					};
+					let scroll = *model.string_view_state.scroll_for(&p);
+					let file_previews = &model.file_previews;
+					let preview = data.file_target().and_then(|x| file_previews.get(x));
+					if let Some(preview) = preview {
+						let title = format!("{} ({})", data.get_type(), preview.title());
+						f.render_widget(block.title(title), outer);
+						render_file_preview(f, &p, preview, scroll, search, inner);
+					} else if let PathData::String(content) = data {
+						let syntax = Syntax::guess(&p, content);
+						let title =
+							format!("String ({}, {} lines)", syntax.name(), line_count(content));
+						f.render_widget(block.title(title), outer);
+						render_string_view(f, content, syntax, scroll, search, inner);
+					} else {
+						f.render_widget(block.title(data.get_type()), outer);
+						render_value_preview(f, &p, data, &model.file_previews, inner);
+					}
+				}
+				x => {
+					let mut current_list_block = current_frame(&columns);
+					let sort = model.sort.mode(&p);
+					if !sort.is_default() {
+						current_list_block = current_list_block
+							.title(format!("by {}", sort.name()))
+							.title_style(theme().dim);
+					}
+					let inner = current_list_block.inner(columns.current);
+					view_data.current_list_height = inner.height;
+					view_data.current_area = inner;
+					f.render_widget(current_list_block, columns.current);
+					if let Some(PathData::List(current_path_data)) = x {
+						render_list(
+							f,
+							current_path_data,
+							inner,
+							Some(&model.search_input),
+							Some(&model.path_navigator_input),
+							&model.prev_tab_completion,
+							diff.as_ref(),
+						);
+					}
+					render_preview(f, model, columns.preview, &p);
 				}
-				let _ = render_preview(f, model, miller_layout[2], &p);
 			}
-		},
-		x @ _ => {
-			let current_list_block = current_frame();
-			let current_inner = current_list_block.inner(miller_layout[1]);
+		}
+		x => {
+			let current_list_block = current_frame(&columns);
+			let current_inner = current_list_block.inner(columns.current);
 			view_data.current_list_height = current_inner.height;
-			f.render_widget(current_list_block, miller_layout[1]);
+			view_data.current_area = current_inner;
+			f.render_widget(current_list_block, columns.current);
 
 			let preview_frame = preview_frame();
-			let preview_inner = preview_frame.inner(miller_layout[2]);
-			f.render_widget(preview_frame, miller_layout[2]);
+			let preview_inner = preview_frame.inner(columns.preview);
+			f.render_widget(preview_frame, columns.preview);
 
 			match x {
 				BrowserStackItem::Root => {
@@ -129,8 +252,17 @@ pub fn view(model: &mut Model, f: &mut Frame) -> ViewData {
 						Some(1) => {
 							render_recents(model, f, preview_inner);
 						}
-						// Root
-						Some(2) => {
+						Some(SERVICES_ENTRY) => {
+							let text = match model.system_config_root() {
+								Some(config) => {
+									format!("Everything enabled in {}", config.to_expr())
+								}
+								None => "No configuration of this host found in the root (yet)"
+									.to_string(),
+							};
+							f.render_widget(Paragraph::new(text).style(theme().dim), preview_inner);
+						}
+						Some(ROOT_ENTRY) => {
 							if let Some(PathData::List(current_list_data)) =
 								model.path_data.get_mut(&BrowserPath::from("".to_string()))
 							{
@@ -141,23 +273,58 @@ pub fn view(model: &mut Model, f: &mut Frame) -> ViewData {
 									Some(&model.search_input),
 									Some(&model.path_navigator_input),
 									&model.prev_tab_completion,
+									None,
 								);
 							}
 						}
-						_ => {}
+						_ => {
+							if let Some(entry) = model.selected_root_entry() {
+								if let Some(data) = model.path_data.get_mut(&entry.path) {
+									render_value_preview(
+										f,
+										&entry.path,
+										data,
+										&model.file_previews,
+										preview_inner,
+									);
+								}
+							}
+						}
 					}
 				}
 				BrowserStackItem::Bookmarks => {
 					render_bookmarks(model, f, current_inner);
 
-					let selected_bookmark_index = model.bookmark_view_state.selected();
-
-					if let Some(bookmark) =
-						selected_bookmark_index.and_then(|i| model.config.bookmarks.get(i))
-					{
+					if let Some(bookmark) = model.selected_bookmark() {
 						let path = bookmark.path.clone();
+						let mut preview_inner = preview_inner;
+						if let Some(note) = &bookmark.note {
+							let width = preview_inner.width.max(1) as usize;
+							let height = note
+								.lines()
+								.map(|x| x.chars().count().div_ceil(width).max(1))
+								.sum::<usize>() + 1;
+							let height = (height as u16).min(preview_inner.height / 2);
+							f.render_widget(
+								Paragraph::new(note.as_str())
+									.style(theme().dim)
+									.wrap(Wrap { trim: false }),
+								Rect {
+									height,
+									..preview_inner
+								},
+							);
+							preview_inner.y += height;
+							preview_inner.height -= height;
+						}
 						if let Some(data) = model.path_data.get_mut(&path) {
-							render_value_preview(f, data, preview_inner);
+							render_value_preview(
+								f,
+								&path,
+								data,
+								&model.file_previews,
+								preview_inner,
+							);
 						}
 					}
 				}
@@ -167,7 +334,43 @@ pub fn view(model: &mut Model, f: &mut Frame) -> ViewData {
 					if let Some(path) = selected_recent_index.and_then(|i| model.recents.get(i)) {
 						let path = path.clone();
 						if let Some(data) = model.path_data.get_mut(&path) {
-							render_value_preview(f, data, preview_inner);
+							render_value_preview(
+								f,
+								&path,
+								data,
+								&model.file_previews,
+								preview_inner,
+							);
+						}
+					}
+				}
+				BrowserStackItem::SystemCompare => {
+					if let Some(compare) = &mut model.system_compare {
+						render_system_compare(f, compare, &model.path_data, current_inner);
+						render_system_compare_preview(f, compare, &model.path_data, preview_inner);
+					}
+				}
+				BrowserStackItem::Services => {
+					if let Some(services) = &mut model.services {
+						render_services(f, services, &model.path_data, current_inner);
+						let selected = services.selected_path(&model.path_data);
+						let path_data = &mut model.path_data;
+						match selected.and_then(|x| Some((path_data.get_mut(&x)?, x))) {
+							Some((data, path)) => {
+								render_value_preview(
+									f,
+									&path,
+									data,
+									&model.file_previews,
+									preview_inner,
+								);
+							}
+							None => render_services_summary(
+								f,
+								services,
+								&model.path_data,
+								preview_inner,
+							),
 						}
 					}
 				}
@@ -176,14 +379,112 @@ pub fn view(model: &mut Model, f: &mut Frame) -> ViewData {
 		}
 	}
 
+	render_bottom_and_overlays(f, model);
+
+	// With the preview zoomed in, the current list is hidden and pages are as high as the preview
+	if columns.current.is_empty() {
+		view_data.current_list_height = view_data.preview_area.height;
+	}
+	view_data
+}
+
+fn render_bottom_and_overlays(f: &mut Frame, model: &mut Model) {
 	let rect = f.size();
 	render_bottom(
 		f,
 		model,
 		Rect::new(rect.x + 1, rect.y + 1, rect.width - 1, rect.height - 1),
 	);
+	if let Some(batch) = &mut model.batch {
+		render_batch(f, batch, &model.path_data, rect);
+	}
+	if model.show_cache_stats {
+		render_cache_stats(f, &model.path_data.stats(), rect);
+	}
+	render_help(f, model, rect);
+}
 
-	view_data
+/// Overlay listing the keys of every mode, starting with the one it was opened from
+pub fn render_help(f: &mut Frame, model: &mut Model, outer: Rect) {
+	let keymap = &model.keymap;
+	let Some(help) = &mut model.help else {
+		return;
+	};
+	let modes = std::iter::once(help.mode)
+		.chain(InputMode::ALL.iter().copied().filter(|x| *x != help.mode));
+	let groups = modes
+		.map(|mode| {
+			let rows = keymap
+				.help(mode)
+				.into_iter()
+				.map(|(action, chords)| {
+					let keys = chords
+						.iter()
+						.map(|x| x.to_string())
+						.collect::<Vec<_>>()
+						.join(", ");
+					(keys, action.label(), action_name(&action))
+				})
+				.chain(
+					fixed_keys(mode)
+						.iter()
+						.map(|(keys, label)| (keys.to_string(), *label, String::new())),
+				)
+				.collect::<Vec<_>>();
+			(mode.name(), rows)
+		})
+		.collect::<Vec<_>>();
+	let rows = || groups.iter().flat_map(|(_, rows)| rows);
+	let key_width = rows()
+		.map(|(keys, _, _)| keys.chars().count())
+		.max()
+		.unwrap_or(0);
+	let label_width = rows().map(|(_, label, _)| label.len()).max().unwrap_or(0);
+	let close = keymap
+		.help(InputMode::Help)
+		.into_iter()
+		.find(|(action, _)| *action == Action::Help)
+		.and_then(|(_, chords)| chords.first().map(|x| x.to_string()))
+		.unwrap_or_default();
+	let mut text: Vec<Line> = vec![];
+	for (name, rows) in &groups {
+		if !text.is_empty() {
+			text.push(Line::default());
+		}
+		text.push(Line::styled(*name, theme().heading));
+		text.extend(rows.iter().map(|(keys, label, name)| {
+			Line::from(vec![
+				Span::styled(format!(" {keys:key_width$}  "), theme().help_key),
+				Span::from(format!("{label:label_width$}  ")),
+				Span::styled(name.as_str(), theme().dim),
+			])
+		}));
+	}
+
+	let width = (key_width + label_width + 30).min(outer.width as usize) as u16;
+	let height = (text.len() + 2).min(outer.height.saturating_sub(2) as usize) as u16;
+	let rect = Rect::new(
+		outer.x + (outer.width - width) / 2,
+		outer.y + (outer.height - height) / 2,
+		width,
+		height,
+	);
+	let block = Block::default()
+		.borders(Borders::ALL)
+		.title(format!("Help ({close} to close)"))
+		.title_style(theme().title);
+	let inner = block.inner(rect);
+	help.half_page = (inner.height as usize / 2).max(1);
+	help.scroll = help
+		.scroll
+		.min(text.len().saturating_sub(inner.height as usize));
+	Clear.render(rect, f.buffer_mut());
+	f.render_widget(
+		Paragraph::new(text)
+			.block(block)
+			.scroll((help.scroll as u16, 0)),
+		rect,
+	);
 }
 
 pub fn render_previous_stack(model: &mut Model, f: &mut Frame, inner: Rect) {
@@ -194,17 +495,42 @@ pub fn render_previous_stack(model: &mut Model, f: &mut Frame, inner: Rect) {
 		Some(BrowserStackItem::Bookmarks) => render_bookmarks(model, f, inner),
 		Some(BrowserStackItem::Root) => render_root(model, f, inner),
 		Some(BrowserStackItem::Recents) => render_recents(model, f, inner),
+		Some(BrowserStackItem::SystemCompare) => {
+			if let Some(compare) = &mut model.system_compare {
+				render_system_compare(f, compare, &model.path_data, inner);
+			}
+		}
+		Some(BrowserStackItem::Services) => {
+			if let Some(services) = &mut model.services {
+				render_services(f, services, &model.path_data, inner);
+			}
+		}
 		None => {}
 	}
 }
 
 pub fn with_selected_style(x: List) -> List {
-	x.highlight_symbol(">>").highlight_style(*SELECTED_STYLE)
+	x.highlight_symbol(">>").highlight_style(theme().selected)
 }
 
 pub fn render_root(model: &mut Model, f: &mut Frame, inner: Rect) {
+	let kind_width = CONFIGURATIONS
+		.iter()
+		.map(|(_, kind)| kind.len())
+		.max()
+		.unwrap_or(0);
+	let discovered = model.root_entries().into_iter().map(|entry| {
+		ListItem::new(Line::from(vec![
+			Span::styled(format!("{:kind_width$}  ", entry.kind), theme().dim),
+			Span::raw(entry.name),
+		]))
+	});
+	let items = ROOT_ENTRIES
+		.iter()
+		.map(|x| ListItem::new(*x))
+		.chain(discovered);
 	f.render_stateful_widget(
-		with_selected_style(List::new(["Bookmarks", "Recents", "Root"])),
+		with_selected_style(List::new(items)),
 		inner,
 		&mut model.root_view_state,
 	);
@@ -219,28 +545,79 @@ pub fn render_recents(model: &mut Model, f: &mut Frame, inner: Rect) {
 }
 
 pub fn render_bookmarks(model: &mut Model, f: &mut Frame, inner: Rect) {
+	let items = model
+		.bookmark_rows()
+		.into_iter()
+		.filter_map(|x| {
+			let x = match x {
+				BookmarkRow::Group {
+					name,
+					collapsed,
+					len,
+				} => {
+					let arrow = if collapsed { "▸" } else { "▾" };
+					return Some(ListItem::new(Line::from(vec![
+						Span::styled(format!("{arrow} {name}"), theme().heading),
+						Span::styled(format!(" ({len})"), theme().dim),
+					])));
+				}
+				BookmarkRow::Bookmark(x) => x,
+			};
+			let bookmark = model.config.bookmarks.get(x, &model.root_id)?;
+			let indent = if bookmark.group.is_some() { "  " } else { "" };
+			let mut spans = vec![Span::raw(format!("{indent}{}", bookmark.name))];
+			if bookmark.note.is_some() {
+				spans.push(Span::styled(" ✎", theme().dim));
+			}
+			if x.scope == BookmarkScope::Global {
+				spans.push(Span::styled(" (global)", theme().dim));
+			}
+			Some(ListItem::new(Line::from(spans)))
+		})
+		.collect::<Vec<_>>();
 	f.render_stateful_widget(
-		with_selected_style(List::new(model.config.bookmarks.clone())),
+		with_selected_style(List::new(items)),
 		inner,
 		&mut model.bookmark_view_state,
 	)
 }
 
-pub fn current_frame<'a>() -> Block<'a> {
-	Block::default()
-		.borders(Borders::ALL)
-		.border_set(symbols::border::Set {
-			top_left: symbols::line::NORMAL.horizontal_down,
-			top_right: symbols::line::NORMAL.horizontal_down,
-			bottom_left: symbols::line::NORMAL.horizontal_up,
-			bottom_right: symbols::line::NORMAL.horizontal_up,
-			..symbols::border::PLAIN
-		})
+/// Borders whose corners join the lines of the columns to the left and right
+fn frame_border_set(join_left: bool, join_right: bool) -> symbols::border::Set {
+	let line = symbols::line::NORMAL;
+	let plain = symbols::border::PLAIN;
+	symbols::border::Set {
+		top_left: if join_left {
+			line.horizontal_down
+		} else {
+			plain.top_left
+		},
+		top_right: if join_right {
+			line.horizontal_down
+		} else {
+			plain.top_right
+		},
+		bottom_left: if join_left {
+			line.horizontal_up
+		} else {
+			plain.bottom_left
+		},
+		bottom_right: if join_right {
+			line.horizontal_up
+		} else {
+			plain.bottom_right
+		},
+		..plain
+	}
 }
 
-lazy_static! {
-	pub static ref SELECTED_STYLE: ratatui::style::Style =
-		Style::default().bg(Color::Yellow).fg(Color::Black);
+pub fn current_frame<'a>(columns: &Columns) -> Block<'a> {
+	Block::default()
+		.borders(Borders::ALL)
+		.border_set(frame_border_set(
+			!columns.parent.is_empty(),
+			!columns.stacked,
+		))
 }
 
 pub fn render_list(
@@ -250,43 +627,65 @@ pub fn render_list(
 	search_input: Option<&InputState>,
 	path_navigator_input: Option<&InputState>,
 	prev_tab_completion: &Option<String>,
+	diff: Option<&ListDiff>,
 ) {
-	let selected_style = *SELECTED_STYLE;
-	let render_list: Vec<_> = list
+	let selected_style = theme().selected;
+	let mut render_list: Vec<_> = list
 		.list
 		.iter()
 		.enumerate()
 		.map(|(i, x)| {
-			let highlight_style = if Some(i) == list.state.selected() {
+			let selected = Some(i) == list.state.selected();
+			let highlight_style = if selected {
 				selected_style
 			} else {
 				Style::default()
 			};
-			match (path_navigator_input, search_input) {
-				(Some(_), Some(InputState::Active(search_model))) => {
-					ListItem::new(highlight_on_match(x.as_str(), search_model.input.as_str()))
-						.style(highlight_style)
-				}
+			let (mut line, style) = match (path_navigator_input, search_input) {
+				(Some(_), Some(InputState::Active(search_model))) => (
+					highlight_on_match(x.as_str(), search_model.input.as_str()),
+					highlight_style,
+				),
 				(Some(InputState::Active(nav_model)), Some(_)) => {
 					let search_str = prev_tab_completion
 						.as_deref()
-						.or_else(|| nav_model.input.split('.').last())
+						.or_else(|| nav_model.input.split('.').next_back())
 						.filter(|x| !x.is_empty());
-					ListItem::new(x.as_str()).style(search_str.map_or(
-						highlight_style,
-						|search_str| {
+					(
+						Line::raw(x.as_str()),
+						search_str.map_or(highlight_style, |search_str| {
 							if x.starts_with(search_str) {
-								Style::default().on_green().fg(Color::Black)
+								theme().completion
 							} else {
 								highlight_style
 							}
-						},
-					))
+						}),
+					)
 				}
-				_ => ListItem::new(x.clone()).style(highlight_style),
+				_ => (Line::raw(x.as_str()), highlight_style),
+			};
+			match diff.and_then(|diff| diff.statuses.get(i)) {
+				Some(status) => {
+					line.spans.insert(0, Span::raw(status.marker()));
+					let style = if selected {
+						style
+					} else {
+						style.patch(status.style())
+					};
+					ListItem::new(line).style(style)
+				}
+				None => ListItem::new(line).style(style),
 			}
 		})
 		.collect();
+	if let Some(diff) = diff {
+		// Attributes that only exist on the right hand side can't be entered, but are listed
+		// after everything else so they are not missed
+		render_list.extend(diff.added.iter().map(|x| {
+			ListItem::new(format!("{}{x}", DiffStatus::Added.marker()))
+				.style(DiffStatus::Added.style())
+		}));
+	}
 
 	f.render_stateful_widget(List::new(render_list), inner, &mut list.state);
 }
@@ -298,7 +697,7 @@ pub fn render_previous_list(
 	inner: Rect,
 	p: &BrowserPath,
 ) {
-	let list = match path_data.get_mut(&p) {
+	let list = match path_data.get_mut(p) {
 		Some(PathData::List(list)) => list,
 		_ => return,
 	};
@@ -311,50 +710,47 @@ pub fn render_previous_list(
 }
 
 pub fn render_keymap(model: &Model, f: &mut Frame, rect: Rect) {
-	let typing = match (&model.path_navigator_input, &model.search_input) {
-		(InputState::Active(m), _) => Some(m.typing),
-		(_, InputState::Active(m)) => Some(m.typing),
-		_ => None,
-	};
-	let keymap: &[(&str, &str)] = match typing {
-		Some(true) => &[("<Enter>", "Confirm"), ("<Esc>", "Exit Search")],
-		Some(false) => &[
-			("n", "Next Occurence"),
-			("N", "Previous Occurence"),
-			("<Esc>", "Exit Search"),
-		],
-		None => &[
-			(".", "Go To Path"),
-			("/", "Find"),
-			("r", "Refresh"),
-			("s", "Save Bookmark"),
-			("d", "Delete Bookmark"),
-			("q", "Quit"),
-			("<C-d>", "Half-page down"),
-			("<C-u>", "Half-page up"),
-		],
-	};
+	let (mode, typing) = input_mode(model);
+	let mut keymap: Vec<(String, &str)> = vec![];
+	if typing && matches!(mode, InputMode::Search | InputMode::Navigator) {
+		keymap.push(("<Enter>".to_string(), "Confirm"));
+	}
+	keymap.extend(
+		model
+			.keymap
+			.footer(mode)
+			.into_iter()
+			// Characters are typed into the input, so those bindings don't apply
+			.filter(|(chord, _)| !(typing && chord.is_text()))
+			.map(|(chord, action)| (chord.to_string(), action.label())),
+	);
 	let texts = keymap
-		.iter()
-		.map(|(key, text)| {
+		.into_iter()
+		.flat_map(|(key, text)| {
 			[
-				key.black().on_gray(),
-				Span::from(format!(" {text} ")).fg(Color::default()),
+				Span::styled(key, theme().key),
+				Span::from(format!(" {text} ")),
 			]
 		})
-		.flatten()
 		.collect::<Vec<_>>();
 	let paragraph = Paragraph::new(Line::from(texts)).alignment(Alignment::Center);
 	f.render_widget(paragraph, rect);
 }
 
+/// The end of `text` that fits in `width` characters, as ratatui has no left overflow
+pub fn clip_left(text: &str, width: usize) -> &str {
+	let skip = text.chars().count().saturating_sub(width);
+	text.char_indices()
+		.nth(skip)
+		.map_or("", |(i, _)| &text[i..])
+}
+
 pub fn render_input<'a>(f: &mut Frame, text: impl Into<Text<'a>>, rect: Rect) {
 	Clear.render(rect, f.buffer_mut());
 	f.render_widget(
 		Paragraph::new(text)
 			.alignment(Alignment::Left)
-			.fg(Color::Gray)
-			.bg(Color::default()),
+			.style(theme().text),
 		rect,
 	);
 }
@@ -371,12 +767,43 @@ pub fn render_bottom(f: &mut Frame, model: &Model, inner: Rect) {
 
 	offset += 1;
 
+	if let Some(status_message) = &model.status_message {
+		render_input(
+			f,
+			status_message.as_str(),
+			Rect::new(inner.left(), inner.bottom() - offset, inner.width, 1),
+		);
+		offset += 1;
+	}
+
+	let diff_text = match (&model.diff, &model.diff_mark) {
+		(Some(diff), _) => Some(format!(
+			"Diff: {} <> {}",
+			diff.left.to_expr(),
+			diff.right.to_expr()
+		)),
+		(None, Some(mark)) => Some(format!(
+			"Diff from: {} (D on another path to compare)",
+			mark.to_expr()
+		)),
+		_ => None,
+	};
+	if let Some(diff_text) = diff_text {
+		let render_text = clip_left(&diff_text, inner.width as usize);
+		render_input(
+			f,
+			render_text,
+			Rect::new(inner.left(), inner.bottom() - offset, inner.width, 1),
+		);
+		offset += 1;
+	}
+
 	// Render the search string in the bottom right corner of the container
 	if let InputState::Active(search_model) = &model.search_input {
 		let render_text = format!("Search: {}", search_model.input.clone());
 		// ratatui does not have a concept of a "right overflow" to my understanding, so clip the
 		// text from the left manually if it starts overflowing
-		let render_text = &render_text[render_text.len().saturating_sub(inner.width as usize)..];
+		let render_text = clip_left(&render_text, inner.width as usize);
 
 		render_input(
 			f,
@@ -387,7 +814,7 @@ pub fn render_bottom(f: &mut Frame, model: &Model, inner: Rect) {
 	}
 	if let InputState::Active(navigator_state) = &model.path_navigator_input {
 		let render_text = format!("Goto: {}", navigator_state.input.clone());
-		let render_text = &render_text[render_text.len().saturating_sub(inner.width as usize)..];
+		let render_text = clip_left(&render_text, inner.width as usize);
 		render_input(
 			f,
 			render_text,
@@ -397,8 +824,12 @@ pub fn render_bottom(f: &mut Frame, model: &Model, inner: Rect) {
 	}
 
 	if let InputState::Active(bookmark_input_state) = &model.new_bookmark_input {
-		let render_text = format!("bookmark name: {}", bookmark_input_state.input.clone());
-		let render_text = &render_text[render_text.len().saturating_sub(inner.width as usize)..];
+		let prompt = match model.bookmark_edit {
+			Some((_, field)) => field.prompt(),
+			None => "bookmark name",
+		};
+		let render_text = format!("{prompt}: {}", bookmark_input_state.input.clone());
+		let render_text = clip_left(&render_text, inner.width as usize);
 		render_input(
 			f,
 			render_text,
@@ -406,9 +837,30 @@ pub fn render_bottom(f: &mut Frame, model: &Model, inner: Rect) {
 		);
 		offset += 1;
 	}
+
+	if let InputState::Active(eval_input_state) = &model.eval_input {
+		let render_text = format!("eval (_ = value under cursor): {}", eval_input_state.input);
+		let render_text = clip_left(&render_text, inner.width as usize);
+		render_input(
+			f,
+			render_text,
+			Rect::new(inner.left(), inner.bottom() - offset, inner.width, 1),
+		);
+	}
 }
 
-pub fn render_value_preview(f: &mut Frame, path_data: &mut PathData, inner: Rect) {
+pub fn render_value_preview(
+	f: &mut Frame,
+	path: &BrowserPath,
+	path_data: &mut PathData,
+	file_previews: &FilePreviews,
+	inner: Rect,
+) {
+	if let Some(preview) = path_data.file_target().and_then(|x| file_previews.get(x)) {
+		render_file_preview(f, path, preview, 0, None, inner);
+		return;
+	}
+
 	match path_data {
 		// NixValue::Attrs(list) => {
 		//     let items = list.iter().map(|(k, _v)| {
@@ -433,14 +885,18 @@ pub fn render_value_preview(f: &mut Frame, path_data: &mut PathData, inner: Rect
 		//     );
 		// }
 		PathData::List(list) => {
-			render_list(f, list, inner, None, None, &None);
+			render_list(f, list, inner, None, None, &None, None);
+		}
+		PathData::String(content) if content.contains('\n') => {
+			let syntax = Syntax::guess(path, content);
+			render_string_view(f, content, syntax, 0, None, inner);
 		}
 		_ => {
 			let value = path_data.to_string();
 			let value = value.into_text().unwrap_or(value.to_string().into());
 			f.render_widget(
 				Paragraph::new(value)
-					.style(Style::new().fg(color_from_type(path_data)))
+					.style(style_from_type(path_data))
 					.wrap(Wrap { trim: true }),
 				inner,
 			);
@@ -451,7 +907,7 @@ pub fn render_value_preview(f: &mut Frame, path_data: &mut PathData, inner: Rect
 pub fn preview_frame<'a>() -> Block<'a> {
 	Block::new()
 		.borders(Borders::RIGHT | Borders::TOP | Borders::BOTTOM)
-		.title_style(Style::new().blue())
+		.title_style(theme().title)
 }
 
 pub fn render_preview(f: &mut Frame, model: &mut Model, outer: Rect, current_path: &BrowserPath) {
@@ -459,15 +915,23 @@ pub fn render_preview(f: &mut Frame, model: &mut Model, outer: Rect, current_pat
 
 	let selected_path = model
 		.path_data
-		.current_list(&current_path)
-		.and_then(|list| list.selected(&current_path));
+		.current_list(current_path)
+		.and_then(|list| list.selected(current_path));
 
 	if let Some(selected_path) = selected_path {
+		let right_path = model
+			.diff
+			.as_ref()
+			.and_then(|diff| diff.counterpart(&selected_path));
+		if let Some(right_path) = right_path {
+			render_diff_preview(f, model, outer, &selected_path, &right_path);
+			return;
+		}
 		if let Some(value) = model.path_data.get_mut(&selected_path) {
 			block = block.title(value.get_type());
 			let inner = block.inner(outer);
 			f.render_widget(block, outer);
-			render_value_preview(f, value, inner);
+			render_value_preview(f, &selected_path, value, &model.file_previews, inner);
 			return;
 		}
 	}
@@ -475,18 +939,76 @@ pub fn render_preview(f: &mut Frame, model: &mut Model, outer: Rect, current_pat
 	f.render_widget(block, outer);
 }
 
-fn color_from_type(value: &PathData) -> Color {
+/// Show both sides of a diff above each other
+fn render_diff_preview(
+	f: &mut Frame,
+	model: &mut Model,
+	outer: Rect,
+	left_path: &BrowserPath,
+	right_path: &BrowserPath,
+) {
+	let halves = Layout::default()
+		.direction(Direction::Vertical)
+		.constraints(vec![Constraint::Ratio(1, 2), Constraint::Ratio(1, 2)])
+		.split(outer);
+
+	render_diff_side(
+		f,
+		"left",
+		left_path,
+		model.path_data.get_mut(left_path),
+		&model.file_previews,
+		halves[0],
+	);
+	let right_data = match &mut model.diff {
+		Some(DiffState {
+			right_data: Some(right_data),
+			..
+		}) => right_data,
+		_ => &mut model.path_data,
+	};
+	render_diff_side(
+		f,
+		"right",
+		right_path,
+		right_data.get_mut(right_path),
+		&model.file_previews,
+		halves[1],
+	);
+}
+
+fn render_diff_side(
+	f: &mut Frame,
+	side: &str,
+	path: &BrowserPath,
+	value: Option<&mut PathData>,
+	file_previews: &FilePreviews,
+	rect: Rect,
+) {
+	let block = preview_frame();
+	match value {
+		Some(value) => {
+			let block = block.title(format!("{side}: {}", value.get_type()));
+			let inner = block.inner(rect);
+			f.render_widget(block, rect);
+			render_value_preview(f, path, value, file_previews, inner);
+		}
+		None => f.render_widget(block.title(format!("{side}: not evaluated")), rect),
+	}
+}
+
+pub fn style_from_type(value: &PathData) -> Style {
+	let theme = theme();
 	match value {
-		// PathData::Attrs(_) => Color::Yellow,
-		PathData::List(_) => Color::Cyan,
-		PathData::Int(_) | PathData::Float(_) => Color::LightBlue,
-		PathData::String(_) => Color::LightRed,
-		PathData::Path(_) => Color::Rgb(187, 159, 252),
-		PathData::Bool(_) => Color::Green,
-		PathData::Function => Color::Magenta,
-		PathData::Thunk => Color::LightMagenta,
-		PathData::Error(_) => Color::Red,
-		_ => Color::default(),
+		PathData::List(_) => theme.list,
+		PathData::Int(_) | PathData::Float(_) => theme.number,
+		PathData::String(_) => theme.string,
+		PathData::Path(_) => theme.path,
+		PathData::Bool(_) => theme.bool,
+		PathData::Function => theme.function,
+		PathData::Thunk => theme.thunk,
+		PathData::Error(_) => theme.error,
+		_ => Style::new(),
 	}
 }
 
@@ -498,10 +1020,7 @@ fn highlight_on_match<'a>(haystack: &'a str, needle: &'a str) -> Line<'a> {
 		if index > last_index {
 			spans.push(Span::raw(&haystack[last_index..index]));
 		}
-		spans.push(Span::styled(
-			needle,
-			Style::new().fg(Color::Black).bg(Color::Blue),
-		));
+		spans.push(Span::styled(needle, theme().search_match));
 		last_index = index + needle.len();
 	}
 
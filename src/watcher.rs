@@ -0,0 +1,80 @@
+use std::{
+	path::{Path, PathBuf},
+	time::Duration,
+};
+
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+
+use crate::model::Message;
+
+/// Editors tend to save a file in several steps, so changes are collected for this long before
+/// reloading
+const DEBOUNCE: Duration = Duration::from_millis(300);
+
+/// Whether a change to `path` can affect evaluation. Version control, build results and editor
+/// temporaries are ignored.
+pub fn is_relevant(path: &Path) -> bool {
+	let ignored_dir = path.components().any(|x| {
+		matches!(
+			x.as_os_str().to_str(),
+			Some(".git") | Some(".direnv") | Some("result")
+		)
+	});
+	if ignored_dir {
+		return false;
+	}
+	let name = match path.file_name().and_then(|x| x.to_str()) {
+		Some(x) => x,
+		None => return true,
+	};
+	!(name.ends_with('~')
+		|| name.ends_with(".swp")
+		|| name.ends_with(".swx")
+		|| name.starts_with(".#")
+		// vim checks whether a directory is writable with this file
+		|| name == "4913")
+}
+
+/// Watch `paths` recursively and send `Message::Reload` once changes below them settle down.
+/// Watching stops when the returned watcher is dropped.
+pub fn watch(paths: &[PathBuf], tx: kanal::Sender<Message>) -> notify::Result<RecommendedWatcher> {
+	let (change_tx, change_rx) = kanal::unbounded::<()>();
+	let mut watcher =
+		notify::recommended_watcher(move |res: notify::Result<notify::Event>| match res {
+			Ok(event) => {
+				if !matches!(event.kind, EventKind::Access(_))
+					&& event.paths.iter().any(|x| is_relevant(x))
+				{
+					let _ = change_tx.send(());
+				}
+			}
+			Err(e) => tracing::error!("Watch error: {e}"),
+		})?;
+	for path in paths {
+		tracing::info!("Watching {}", path.display());
+		watcher.watch(path, RecursiveMode::Recursive)?;
+	}
+
+	std::thread::spawn(move || {
+		while change_rx.recv().is_ok() {
+			while change_rx.recv_timeout(DEBOUNCE).is_ok() {}
+			if tx.send(Message::Reload).is_err() {
+				break;
+			}
+		}
+	});
+
+	Ok(watcher)
+}
+
+#[test]
+pub fn test_is_relevant() {
+	assert!(is_relevant(Path::new("/etc/nixos/configuration.nix")));
+	assert!(is_relevant(Path::new("/home/me/flake/flake.lock")));
+	assert!(!is_relevant(Path::new("/home/me/flake/.git/index")));
+	assert!(!is_relevant(Path::new("/home/me/flake/result")));
+	assert!(!is_relevant(Path::new(
+		"/home/me/flake/hosts/.default.nix.swp"
+	)));
+	assert!(!is_relevant(Path::new("/home/me/flake/hosts/default.nix~")));
+}
@@ -69,7 +69,7 @@ impl WorkerHost {
 						result_tx
 							.send((path.clone(), PathData::Loading))
 							.expect("Failed to send loading state");
-						if let Err(e) = writeln!(stdin, "{}", path.to_expr()) {
+						if let Err(e) = writeln!(stdin, "{}", path.to_attr_path()) {
 							tracing::error!("Failed to send path, {e}");
 							break;
 						}
@@ -109,6 +109,7 @@ impl WorkerHost {
 			}
 
 			child.kill().expect("killing child failed");
+			let _ = child.wait();
 		});
 
 		WorkerHost { tx, rx: result_rx }
@@ -2,8 +2,8 @@
 
 #include <nix/config.h>
 
+#include <algorithm>
 #include <memory>
-#include <nix/attr-path.hh>
 #include <nix/canon-path.hh>
 #include <nix/command.hh>
 #include <nix/eval-gc.hh>
@@ -14,6 +14,7 @@
 #include <nix/store-api.hh>
 #include <nix/value-to-json.hh>
 #include <nix/value.hh>
+#include <nlohmann/json.hpp>
 #include <string>
 
 #include "logging.hh"
@@ -67,18 +68,39 @@ NixInspector::NixInspector(std::string expr)
 //   return vRes;
 // }
 
+// Selects the names of a JSON array one by one, so names containing `.`, `"` or
+// anything else are taken as they are. Names select list items by their index.
 std::shared_ptr<Value> NixInspector::inspect(std::string &attrPath) {
-  // if (attrPath.length() == 0) {
-  //   attrPath = "root";
-  // } else {
-  //   attrPath = "root." + attrPath;
-  // }
-  Value &v(
-      *findAlongAttrPath(*state, std::string(attrPath), autoArgs, vRoot).first
-  );
-  state->forceValue(v, v.determinePos(noPos));
+  auto names = nlohmann::json::parse(attrPath).get<std::vector<std::string>>();
+  Value *v = &vRoot;
+  for (auto &name : names) {
+    Value *vCalled = state->allocValue();
+    state->autoCallFunction(autoArgs, *v, *vCalled);
+    v = vCalled;
+    state->forceValue(*v, v->determinePos(noPos));
+    if (v->type() == nAttrs) {
+      auto attr = v->attrs()->get(state->symbols.create(name));
+      if (!attr) throw Error("attribute '%s' missing", name);
+      v = attr->value;
+    } else if (v->type() == nList) {
+      bool isIndex = !name.empty() &&
+                     std::all_of(name.begin(), name.end(), ::isdigit);
+      size_t index = isIndex ? std::stoul(name) : 0;
+      if (!isIndex || index >= v->listSize())
+        throw Error("list index '%s' is out of range", name);
+      for (auto item : v->listItems()) {
+        if (index-- == 0) {
+          v = item;
+          break;
+        }
+      }
+    } else {
+      throw Error("cannot select '%s' from a %s", name, showType(*v));
+    }
+  }
+  state->forceValue(*v, v->determinePos(noPos));
   Value vRes;
-  state->autoCallFunction(autoArgs, v, vRes);
+  state->autoCallFunction(autoArgs, *v, vRes);
   return std::make_shared<Value>(vRes);
 }
 